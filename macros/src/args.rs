@@ -0,0 +1,659 @@
+//! Parsing of the `#[styles(...)]` attribute arguments.
+
+use proc_macro2::Span;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Ident, Lit, LitBool, LitStr, Token};
+
+/// The arguments accepted by `#[styles(...)]`.
+#[derive(Clone)]
+pub(crate) struct StylesArgs {
+    pub(crate) source: Source,
+    /// Tag name of the element wrapping the component, `div` by default.
+    pub(crate) element: String,
+    /// Whether to wrap the component at all, rather than scoping the root
+    /// element of its `view!`.
+    pub(crate) wrapper: bool,
+    pub(crate) strategy: Strategy,
+    pub(crate) inject: Inject,
+    /// The writing direction the stylesheet is mirrored for.
+    pub(crate) dir: Direction,
+    /// Prepended to the generated scope ID, as `<prefix>-<name><hash>`.
+    pub(crate) prefix: Option<String>,
+    /// A scope ID replacing the generated one.
+    pub(crate) scope: Option<String>,
+    /// Whether `scope = false` asked for the stylesheet to be left unscoped.
+    pub(crate) global: bool,
+    /// CSS custom properties set on the scope element from Rust values.
+    pub(crate) vars: Vec<Var>,
+    /// Attributes set on the scope element from props of the component.
+    pub(crate) forward: Vec<Forward>,
+    pub(crate) root: PathRoot,
+    /// The `classes` flag, asking for a module of class name constants.
+    pub(crate) classes: Option<Ident>,
+    /// The `class_prop` flag, asking for a `class` prop merged into the scope
+    /// element's class.
+    pub(crate) class_prop: Option<Ident>,
+    /// The `scope_keyframes` flag, renaming the stylesheet's `@keyframes`.
+    pub(crate) scope_keyframes: Option<Ident>,
+    /// The `scope_vars` flag, renaming the custom properties the stylesheet
+    /// declares.
+    pub(crate) scope_vars: Option<Ident>,
+    /// The `flatten` flag, rewriting nested rules as top-level rules.
+    pub(crate) flatten: Option<Ident>,
+    /// The `keep_comments` flag, embedding the stylesheet's comments.
+    pub(crate) keep_comments: Option<Ident>,
+    /// The `dedupe` flag, dropping rules repeated verbatim.
+    pub(crate) dedupe: Option<Ident>,
+    /// The `allow_empty` flag, accepting a stylesheet with no CSS in it.
+    pub(crate) allow_empty: Option<Ident>,
+    /// Stylesheets replacing `source` when a Cargo feature is enabled, in
+    /// order of precedence.
+    pub(crate) features: Vec<FeatureVariant>,
+    /// The `/* @theme ... */` section of the stylesheet to keep.
+    pub(crate) theme: Option<LitStr>,
+}
+
+/// An entry of `features(...)`: `dark = "dark.css"`, or
+/// `"high-contrast" = "contrast.css"` for feature names that are not
+/// identifiers.
+#[derive(Clone)]
+pub(crate) struct FeatureVariant {
+    pub(crate) feature: LitStr,
+    pub(crate) path: LitStr,
+}
+
+impl Parse for FeatureVariant {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let feature = if input.peek(LitStr) {
+            input.parse()?
+        } else {
+            let ident = input.call(Ident::parse_any)?;
+            LitStr::new(&ident.to_string(), ident.span())
+        };
+        input.parse::<Token![=]>()?;
+        let path = input.parse()?;
+        Ok(FeatureVariant { feature, path })
+    }
+}
+
+/// What stylesheet paths are relative to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PathRoot {
+    /// The directory of the source file containing the macro, like
+    /// `include_str!`.
+    File,
+    /// The `CARGO_MANIFEST_DIR` of the crate.
+    Manifest,
+}
+
+impl PathRoot {
+    /// What paths are relative to, for diagnostics.
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            PathRoot::File => "the source file",
+            PathRoot::Manifest => "CARGO_MANIFEST_DIR",
+        }
+    }
+}
+
+/// A custom property of `vars(...)`: `accent` or `accent = theme.accent()`.
+#[derive(Clone)]
+pub(crate) struct Var {
+    pub(crate) name: Ident,
+    pub(crate) value: Expr,
+}
+
+impl Var {
+    /// The CSS name of the property, `--text-color` for `text_color`.
+    pub(crate) fn property(&self) -> String {
+        format!("--{}", self.name.to_string().replace('_', "-"))
+    }
+}
+
+impl Parse for Var {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            input.parse()?
+        } else {
+            syn::parse_quote!(#name)
+        };
+        Ok(Var { name, value })
+    }
+}
+
+/// An attribute of `forward(...)`, such as `data-active`, set on the scope
+/// element from the prop of the same name in snake case, `data_active`.
+#[derive(Clone)]
+pub(crate) struct Forward {
+    pub(crate) attribute: String,
+    pub(crate) prop: Ident,
+}
+
+impl Parse for Forward {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let first = input.call(Ident::parse_any)?;
+        let mut attribute = first.to_string();
+        while input.peek(Token![-]) {
+            input.parse::<Token![-]>()?;
+            attribute.push('-');
+            attribute.push_str(&input.call(Ident::parse_any)?.to_string());
+        }
+        if attribute == "class" || attribute == "style" {
+            return Err(syn::Error::new(
+                first.span(),
+                format!(
+                    "`{}` cannot be forwarded, as the scope element sets it",
+                    attribute
+                ),
+            ));
+        }
+        let prop = syn::parse_str::<Ident>(&attribute.replace('-', "_")).map_err(|_| {
+            syn::Error::new(
+                first.span(),
+                format!("`{}` cannot be turned into a prop name", attribute),
+            )
+        })?;
+        Ok(Forward {
+            prop: Ident::new(&prop.to_string(), first.span()),
+            attribute,
+        })
+    }
+}
+
+/// How the scope is attached to the component's markup.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// A class on the scope element, used as an ancestor selector.
+    Class,
+    /// An attribute on every element of the component, appended to each
+    /// selector like Svelte does.
+    Attribute,
+}
+
+/// When the stylesheet is added to the document.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inject {
+    /// Added with the first instance, and kept for the rest of the app.
+    Eager,
+    /// Added to the `<head>` while at least one instance is mounted.
+    Mount,
+}
+
+/// The horizontal direction of the component's layout.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// The stylesheet as written.
+    Ltr,
+    /// The stylesheet with left and right swapped.
+    Rtl,
+}
+
+/// Where the stylesheet of a component comes from.
+#[derive(Clone)]
+pub(crate) enum Source {
+    /// `#[styles("src/base.css", "src/card.css")]`, concatenated in order.
+    /// Only empty on a module whose components each use their own file.
+    Files(Vec<LitStr>),
+    /// `#[styles(css = ".card { color: red; }")]`
+    Inline(LitStr),
+}
+
+/// The input of `styled!(...)`: the same options as `#[styles(...)]`, then the
+/// expression producing the view to scope.
+pub(crate) struct StyledInput {
+    pub(crate) args: StylesArgs,
+    pub(crate) view: Expr,
+}
+
+impl Parse for StyledInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let args = StylesArgs::parse_options(input, true, false)?;
+        let view = input.parse()?;
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+        Ok(StyledInput { args, view })
+    }
+}
+
+/// The arguments of `#[styles(...)]` on a `mod`: the options of a component,
+/// applied to each component of the module, with the stylesheet optional.
+pub(crate) struct ModuleArgs {
+    pub(crate) args: StylesArgs,
+    /// Whether no stylesheet was given, so that each component is styled with
+    /// the file named after it.
+    pub(crate) by_convention: bool,
+}
+
+impl Parse for ModuleArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let args = StylesArgs::parse_options(input, false, true)?;
+        let by_convention = matches!(&args.source, Source::Files(paths) if paths.is_empty());
+        Ok(ModuleArgs {
+            args,
+            by_convention,
+        })
+    }
+}
+
+impl Parse for StylesArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Self::parse_options(input, false, false)
+    }
+}
+
+impl StylesArgs {
+    /// Parses comma-separated options. With `until_expr`, stops before the
+    /// first item that is not an option, leaving it for the caller. With
+    /// `optional_source`, a missing stylesheet gives an empty list of files.
+    fn parse_options(
+        input: ParseStream,
+        until_expr: bool,
+        optional_source: bool,
+    ) -> syn::Result<Self> {
+        let mut paths: Vec<LitStr> = Vec::new();
+        let mut inline: Option<LitStr> = None;
+        let mut element: Option<LitStr> = None;
+        let mut wrapper: Option<LitBool> = None;
+        let mut strategy: Option<(Strategy, Span)> = None;
+        let mut inject: Option<Inject> = None;
+        let mut dir: Option<Direction> = None;
+        let mut prefix: Option<LitStr> = None;
+        let mut scope: Option<Lit> = None;
+        let mut vars: Option<Vec<Var>> = None;
+        let mut forward: Option<Vec<Forward>> = None;
+        let mut root: Option<PathRoot> = None;
+        let mut classes: Option<Ident> = None;
+        let mut class_prop: Option<Ident> = None;
+        let mut scope_keyframes: Option<Ident> = None;
+        let mut scope_vars: Option<Ident> = None;
+        let mut flatten: Option<Ident> = None;
+        let mut keep_comments: Option<Ident> = None;
+        let mut dedupe: Option<Ident> = None;
+        let mut allow_empty: Option<Ident> = None;
+        let mut features: Option<Vec<FeatureVariant>> = None;
+        let mut theme: Option<LitStr> = None;
+
+        while !input.is_empty() {
+            if until_expr && !is_option(input) {
+                break;
+            }
+            if input.peek(LitStr) {
+                paths.push(input.parse()?);
+            } else {
+                let key: Ident = input.parse()?;
+                if key == "classes" {
+                    set_once(&mut classes, &key, key.clone())?;
+                } else if key == "class_prop" {
+                    set_once(&mut class_prop, &key, key.clone())?;
+                } else if key == "scope_keyframes" {
+                    set_once(&mut scope_keyframes, &key, key.clone())?;
+                } else if key == "scope_vars" {
+                    set_once(&mut scope_vars, &key, key.clone())?;
+                } else if key == "flatten" {
+                    set_once(&mut flatten, &key, key.clone())?;
+                } else if key == "keep_comments" {
+                    set_once(&mut keep_comments, &key, key.clone())?;
+                } else if key == "dedupe" {
+                    set_once(&mut dedupe, &key, key.clone())?;
+                } else if key == "allow_empty" {
+                    set_once(&mut allow_empty, &key, key.clone())?;
+                } else if key == "features" {
+                    let content;
+                    syn::parenthesized!(content in input);
+                    let list = Punctuated::<FeatureVariant, Token![,]>::parse_terminated(&content)?;
+                    set_once(&mut features, &key, list.into_iter().collect())?;
+                } else if key == "vars" {
+                    let content;
+                    syn::parenthesized!(content in input);
+                    let list = Punctuated::<Var, Token![,]>::parse_terminated(&content)?;
+                    set_once(&mut vars, &key, list.into_iter().collect())?;
+                } else if key == "forward" {
+                    let content;
+                    syn::parenthesized!(content in input);
+                    let list = Punctuated::<Forward, Token![,]>::parse_terminated(&content)?;
+                    set_once(&mut forward, &key, list.into_iter().collect())?;
+                } else {
+                    input.parse::<Token![=]>()?;
+                    match key.to_string().as_str() {
+                        "css" => set_once(&mut inline, &key, input.parse()?)?,
+                        "theme" => set_once(&mut theme, &key, input.parse()?)?,
+                        "element" => {
+                            let lit: LitStr = input.parse()?;
+                            validate_element(&lit)?;
+                            set_once(&mut element, &key, lit)?;
+                        }
+                        "wrapper" => set_once(&mut wrapper, &key, input.parse()?)?,
+                        "prefix" => {
+                            let lit: LitStr = input.parse()?;
+                            validate_identifier(&lit)?;
+                            set_once(&mut prefix, &key, lit)?;
+                        }
+                        "scope" => {
+                            let lit = match input.parse()? {
+                                Lit::Str(name) => {
+                                    validate_identifier(&name)?;
+                                    Lit::Str(name)
+                                }
+                                Lit::Bool(global) => Lit::Bool(global),
+                                other => {
+                                    return Err(syn::Error::new(
+                                        other.span(),
+                                        "expected a scope ID or `false`",
+                                    ))
+                                }
+                            };
+                            set_once(&mut scope, &key, lit)?;
+                        }
+                        "strategy" => {
+                            let lit: LitStr = input.parse()?;
+                            let value = match lit.value().as_str() {
+                                "class" => Strategy::Class,
+                                "attribute" => Strategy::Attribute,
+                                other => {
+                                    return Err(syn::Error::new(
+                                        lit.span(),
+                                        format!(
+                                        "unknown strategy `{}`, expected `class` or `attribute`",
+                                        other
+                                    ),
+                                    ))
+                                }
+                            };
+                            set_once(&mut strategy, &key, (value, lit.span()))?;
+                        }
+                        "inject" => {
+                            let lit: LitStr = input.parse()?;
+                            let value = match lit.value().as_str() {
+                                "eager" => Inject::Eager,
+                                "mount" => Inject::Mount,
+                                other => {
+                                    return Err(syn::Error::new(
+                                        lit.span(),
+                                        format!(
+                                            "unknown inject mode `{}`, expected `eager` or `mount`",
+                                            other
+                                        ),
+                                    ))
+                                }
+                            };
+                            set_once(&mut inject, &key, value)?;
+                        }
+                        "dir" => {
+                            let lit: LitStr = input.parse()?;
+                            let value = match lit.value().as_str() {
+                                "ltr" => Direction::Ltr,
+                                "rtl" => Direction::Rtl,
+                                other => {
+                                    return Err(syn::Error::new(
+                                        lit.span(),
+                                        format!(
+                                            "unknown direction `{}`, expected `ltr` or `rtl`",
+                                            other
+                                        ),
+                                    ))
+                                }
+                            };
+                            set_once(&mut dir, &key, value)?;
+                        }
+                        "root" => {
+                            let lit: LitStr = input.parse()?;
+                            let value = match lit.value().as_str() {
+                                "file" => PathRoot::File,
+                                "manifest" => PathRoot::Manifest,
+                                other => {
+                                    return Err(syn::Error::new(
+                                        lit.span(),
+                                        format!(
+                                            "unknown root `{}`, expected `file` or `manifest`",
+                                            other
+                                        ),
+                                    ))
+                                }
+                            };
+                            set_once(&mut root, &key, value)?;
+                        }
+                        _ => {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                format!("unknown `styles` option `{}`", key),
+                            ))
+                        }
+                    }
+                }
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        let source = match (paths.is_empty(), inline) {
+            (false, None) => Source::Files(paths),
+            (true, Some(css)) => Source::Inline(css),
+            (false, Some(css)) => {
+                return Err(syn::Error::new(
+                    css.span(),
+                    "expected either a stylesheet path or `css = \"...\"`, not both",
+                ))
+            }
+            (true, None) if optional_source => Source::Files(Vec::new()),
+            (true, None) => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "expected a stylesheet path or `css = \"...\"`",
+                ))
+            }
+        };
+
+        let (scope, global) = match scope {
+            Some(Lit::Str(name)) => {
+                if let Some(prefix) = &prefix {
+                    return Err(syn::Error::new(
+                        prefix.span(),
+                        "`prefix` has no effect with `scope`, which sets the whole scope ID",
+                    ));
+                }
+                (Some(name.value()), false)
+            }
+            Some(Lit::Bool(scoped)) => (None, !scoped.value),
+            _ => (None, false),
+        };
+        if global {
+            // Options shaping how the component is scoped.
+            let scoping = [
+                element.as_ref().map(|lit| ("element", lit.span())),
+                wrapper.as_ref().map(|lit| ("wrapper", lit.span())),
+                strategy.map(|(_, span)| ("strategy", span)),
+                vars.as_ref()
+                    .and_then(|vars| vars.first())
+                    .map(|var| ("vars", var.name.span())),
+                forward
+                    .as_ref()
+                    .and_then(|forward| forward.first())
+                    .map(|attribute| ("forward", attribute.prop.span())),
+                class_prop.as_ref().map(|key| ("class_prop", key.span())),
+                scope_keyframes
+                    .as_ref()
+                    .map(|key| ("scope_keyframes", key.span())),
+                scope_vars.as_ref().map(|key| ("scope_vars", key.span())),
+            ];
+            if let Some((option, span)) = scoping.into_iter().flatten().next() {
+                return Err(syn::Error::new(
+                    span,
+                    format!("`{}` has no effect with `scope = false`", option),
+                ));
+            }
+        }
+
+        let wrapper = wrapper.is_none_or(|lit| lit.value);
+        if let (false, Some(element)) = (wrapper, &element) {
+            return Err(syn::Error::new(
+                element.span(),
+                "`element` has no effect with `wrapper = false`",
+            ));
+        }
+
+        Ok(StylesArgs {
+            source,
+            element: element.map_or_else(|| "div".to_string(), |lit| lit.value()),
+            wrapper,
+            strategy: strategy.map_or(Strategy::Class, |(strategy, _)| strategy),
+            inject: inject.unwrap_or(Inject::Eager),
+            dir: dir.unwrap_or(Direction::Ltr),
+            prefix: prefix.map(|lit| lit.value()),
+            scope,
+            global,
+            vars: vars.unwrap_or_default(),
+            forward: forward.unwrap_or_default(),
+            root: root.unwrap_or(PathRoot::File),
+            classes,
+            class_prop,
+            scope_keyframes,
+            scope_vars,
+            flatten,
+            keep_comments,
+            dedupe,
+            allow_empty,
+            features: features.unwrap_or_default(),
+            theme,
+        })
+    }
+}
+
+/// Options given without a value.
+const FLAGS: &[&str] = &[
+    "classes",
+    "class_prop",
+    "scope_keyframes",
+    "scope_vars",
+    "flatten",
+    "keep_comments",
+    "dedupe",
+    "allow_empty",
+];
+
+/// Whether the next item of `input` is an option rather than an expression.
+fn is_option(input: ParseStream) -> bool {
+    let fork = input.fork();
+    if fork.peek(LitStr) {
+        return fork.peek2(Token![,]);
+    }
+    match fork.parse::<Ident>() {
+        Ok(key) if key == "vars" || key == "features" || key == "forward" => {
+            fork.peek(syn::token::Paren)
+        }
+        Ok(key) if FLAGS.iter().any(|flag| key == flag) => fork.peek(Token![,]),
+        Ok(_) => fork.peek(Token![=]) && !fork.peek(Token![==]),
+        Err(_) => false,
+    }
+}
+
+/// Stores the value of an option, rejecting options given more than once.
+fn set_once<T>(slot: &mut Option<T>, key: &Ident, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(
+            key.span(),
+            format!("duplicate `{}` option", key),
+        ));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Elements that cannot have children, and so cannot wrap a component.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Checks that a scope ID given in the attribute is usable as a class name,
+/// attribute name and HTML id as is: ASCII letters, digits, `-` and `_`, not
+/// starting with a digit or `-`.
+fn validate_identifier(lit: &LitStr) -> syn::Result<()> {
+    let name = lit.value();
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(syn::Error::new(
+            lit.span(),
+            format!(
+                "`{}` is not a valid scope ID; use ASCII letters, digits, `-` and `_`, such as `{}`",
+                name,
+                crate::load::identifier(&name)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `element` names an HTML element able to wrap a component: a
+/// lowercase tag name such as `span`, or a custom element such as `my-card`.
+fn validate_element(element: &LitStr) -> syn::Result<()> {
+    let name = element.value();
+    let plausible = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && !name.ends_with('-')
+        && !name.contains("--")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !plausible {
+        return Err(syn::Error::new(
+            element.span(),
+            format!("`{}` is not a valid HTML element name", name),
+        ));
+    }
+    if VOID_ELEMENTS.contains(&name.as_str()) {
+        return Err(syn::Error::new(
+            element.span(),
+            format!(
+                "`<{}>` cannot have children, so it cannot wrap a component",
+                name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(args: &str) -> String {
+        match syn::parse_str::<StylesArgs>(args) {
+            Ok(_) => panic!("`{}` was accepted", args),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn scope_ids_may_start_and_end_with_underscores() {
+        for scope in ["_card", "card_", "my-card", "Card2", "_"] {
+            let args = format!("\"card.css\", scope = \"{}\"", scope);
+            let parsed = syn::parse_str::<StylesArgs>(&args).unwrap();
+            assert_eq!(parsed.scope.as_deref(), Some(scope));
+        }
+        syn::parse_str::<StylesArgs>("\"card.css\", prefix = \"_ui_\"").unwrap();
+    }
+
+    #[test]
+    fn rejects_scope_ids_that_are_not_identifiers() {
+        for scope in ["2col", "-card", "my card", "", "ca.rd"] {
+            let message = error(&format!("\"card.css\", scope = \"{}\"", scope));
+            assert!(
+                message.contains("is not a valid scope ID"),
+                "{}: {}",
+                scope,
+                message
+            );
+        }
+    }
+}
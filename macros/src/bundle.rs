@@ -0,0 +1,301 @@
+//! Extraction of the scoped CSS of every component into one stylesheet.
+//!
+//! Each scope is written to a file of its own in a `scopes` directory next to
+//! the bundle, named after its ID and a key for the component writing it, and
+//! the bundle is then rebuilt from all of them, sorted by ID. Components of
+//! separate crates are compiled by separate processes, possibly at the same
+//! time, so nothing is kept in memory: whichever invocation finishes last
+//! writes a bundle including every scope written before it.
+//!
+//! A scope ID is derived from the content of its stylesheet, so two
+//! invocations writing the same ID write the same CSS, and the scope appears
+//! once in the bundle. The order of the bundle does not depend on the order
+//! of compilation.
+//!
+//! The key stays the same when the stylesheet is edited, so the scope of the
+//! previous revision is replaced rather than kept. Beside each scope, a
+//! `.src` file lists the source file of the component and the stylesheets it
+//! was read from; once one of them is deleted, the scope is dropped.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The environment variable naming the bundle file, relative to the root of
+/// the workspace of the crate being compiled.
+const BUNDLE_VAR: &str = "LEPTOS_STYLES_BUNDLE";
+
+/// Records `css` as the stylesheet of the scope `id`, written by the
+/// component `key` stands for and read from the files `sources`, and rebuilds
+/// the bundle.
+pub(crate) fn write(id: &str, key: &str, css: &str, sources: &[String]) -> io::Result<()> {
+    write_to(&bundle_path(), id, key, css, sources)
+}
+
+fn write_to(bundle: &Path, id: &str, key: &str, css: &str, sources: &[String]) -> io::Result<()> {
+    let scopes = scopes_dir(bundle);
+    fs::create_dir_all(&scopes)?;
+    for scope in scopes_in(&scopes)? {
+        if scope.key == key && scope.id != id {
+            scope.remove()?;
+        }
+    }
+    let name = format!("{}.{}", id, key);
+    write_atomically(&scopes.join(format!("{}.src", name)), &sources.join("\n"))?;
+    write_atomically(&scopes.join(format!("{}.css", name)), css)?;
+
+    // Another invocation may add a scope after this one listed the directory,
+    // and write its bundle before this one does. Listing it again once the
+    // bundle is written catches up with any such scope, as the last
+    // invocation to write always finds the bundle up to date.
+    let mut written = None;
+    loop {
+        let contents = contents(&scopes)?;
+        if written.as_ref() == Some(&contents) {
+            return Ok(());
+        }
+        write_atomically(bundle, &contents)?;
+        written = Some(contents);
+    }
+}
+
+/// The bundle file: `LEPTOS_STYLES_BUNDLE` if set, `leptos_styles/bundle.css`
+/// in the target directory otherwise.
+fn bundle_path() -> PathBuf {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let root = workspace_root(&manifest_dir);
+    match std::env::var_os(BUNDLE_VAR) {
+        Some(path) => root.join(path),
+        None => std::env::var_os("CARGO_TARGET_DIR")
+            .map_or_else(|| root.join("target"), |target| root.join(target))
+            .join("leptos_styles")
+            .join("bundle.css"),
+    }
+}
+
+/// Returns the root of the workspace the crate at `manifest_dir` belongs to:
+/// the closest directory holding it whose manifest has a `[workspace]`, where
+/// cargo puts the target directory. A crate outside of any workspace is its
+/// own root.
+fn workspace_root(manifest_dir: &Path) -> PathBuf {
+    manifest_dir
+        .ancestors()
+        .find(|dir| {
+            fs::read_to_string(dir.join("Cargo.toml"))
+                .is_ok_and(|manifest| manifest.lines().any(|line| line.trim() == "[workspace]"))
+        })
+        .unwrap_or(manifest_dir)
+        .to_path_buf()
+}
+
+fn scopes_dir(bundle: &Path) -> PathBuf {
+    bundle.with_file_name("scopes")
+}
+
+/// A scope file of the `scopes` directory, `<id>.<key>.css`.
+struct ScopeFile {
+    path: PathBuf,
+    id: String,
+    key: String,
+}
+
+impl ScopeFile {
+    fn sources(&self) -> PathBuf {
+        self.path.with_extension("src")
+    }
+
+    /// Removes the scope, which another invocation may have done already.
+    fn remove(&self) -> io::Result<()> {
+        for path in [&self.path, &self.sources()] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the scope files of `scopes`, sorted by ID and key.
+fn scopes_in(scopes: &Path) -> io::Result<Vec<ScopeFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(scopes)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Neither IDs nor keys contain dots, so temporary files and files of
+        // other versions do not match.
+        let parts: Vec<&str> = name.split('.').collect();
+        if let [id, key, "css"] = parts[..] {
+            files.push(ScopeFile {
+                id: id.to_string(),
+                key: key.to_string(),
+                path: path.clone(),
+            });
+        }
+    }
+    files.sort_by(|a, b| (&a.id, &a.key).cmp(&(&b.id, &b.key)));
+    Ok(files)
+}
+
+/// Returns the contents of the bundle: the CSS of every scope whose sources
+/// still exist, once per ID. Scopes whose sources are gone are removed.
+fn contents(scopes: &Path) -> io::Result<String> {
+    let mut contents = String::new();
+    let mut last_id = None;
+    for scope in scopes_in(scopes)? {
+        if last_id.as_ref() == Some(&scope.id) {
+            continue;
+        }
+        // Another invocation may replace or remove the scope meanwhile.
+        let read = fs::read_to_string(scope.sources())
+            .and_then(|sources| Ok((sources, fs::read_to_string(&scope.path)?)));
+        let (sources, css) = match read {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if !sources.lines().all(|source| Path::new(source).exists()) {
+            scope.remove()?;
+            continue;
+        }
+        contents.push_str(&css);
+        contents.push('\n');
+        last_id = Some(scope.id);
+    }
+    Ok(contents)
+}
+
+/// Writes `contents` through a temporary file, so that a process reading
+/// `path` at the same time never sees it half-written.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bundle in a directory of its own, removed once the test is over.
+    struct Bundle(PathBuf);
+
+    impl Bundle {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("leptos_styles-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn path(&self) -> PathBuf {
+            self.0.join("bundle.css")
+        }
+
+        fn source(&self, name: &str) -> String {
+            let path = self.0.join(name);
+            fs::write(&path, "").unwrap();
+            path.to_string_lossy().into_owned()
+        }
+
+        fn write(&self, id: &str, key: &str, css: &str, sources: &[String]) {
+            write_to(&self.path(), id, key, css, sources).unwrap();
+        }
+
+        fn read(&self) -> String {
+            fs::read_to_string(self.path()).unwrap()
+        }
+    }
+
+    impl Drop for Bundle {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_the_workspace_of_a_member() {
+        let bundle = Bundle::new("workspace");
+        let member = bundle.0.join("crates").join("app");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(
+            bundle.0.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(member.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        assert_eq!(workspace_root(&member), bundle.0);
+        assert_eq!(workspace_root(&bundle.0), bundle.0);
+    }
+
+    #[test]
+    fn a_crate_outside_of_a_workspace_is_its_own_root() {
+        let bundle = Bundle::new("standalone");
+        fs::write(bundle.0.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        assert_eq!(workspace_root(&bundle.0), bundle.0);
+    }
+
+    #[test]
+    fn bundles_every_scope_once_sorted_by_id() {
+        let bundle = Bundle::new("sorted");
+        let sources = [bundle.source("card.rs")];
+        bundle.write("card2", "aaaa", ".card2 {}", &sources);
+        bundle.write("badge1", "bbbb", ".badge1 {}", &sources);
+        bundle.write("card2", "cccc", ".card2 {}", &sources);
+        assert_eq!(bundle.read(), ".badge1 {}\n.card2 {}\n");
+    }
+
+    #[test]
+    fn replaces_the_previous_revision_of_a_scope() {
+        let bundle = Bundle::new("revision");
+        let sources = [bundle.source("card.rs")];
+        bundle.write("card1", "aaaa", ".card1 { color: red; }", &sources);
+        bundle.write("card2", "aaaa", ".card2 { color: blue; }", &sources);
+        assert_eq!(bundle.read(), ".card2 { color: blue; }\n");
+        let scopes = fs::read_dir(scopes_dir(&bundle.path())).unwrap().count();
+        assert_eq!(scopes, 2);
+    }
+
+    #[test]
+    fn drops_scopes_whose_sources_are_deleted() {
+        let bundle = Bundle::new("deleted");
+        let card = bundle.source("card.rs");
+        let badge = bundle.source("badge.css");
+        bundle.write("card1", "aaaa", ".card1 {}", std::slice::from_ref(&card));
+        bundle.write("badge1", "bbbb", ".badge1 {}", &[card, badge.clone()]);
+        fs::remove_file(badge).unwrap();
+        bundle.write("list1", "cccc", ".list1 {}", &[]);
+        assert_eq!(bundle.read(), ".card1 {}\n.list1 {}\n");
+        assert!(!scopes_dir(&bundle.path()).join("badge1.bbbb.css").exists());
+    }
+
+    #[test]
+    fn concurrent_writes_lose_no_scope() {
+        let bundle = Bundle::new("concurrent");
+        let path = bundle.path();
+        std::thread::scope(|threads| {
+            for thread in 0..8 {
+                let path = &path;
+                threads.spawn(move || {
+                    for scope in 0..10 {
+                        let id = format!("s{}x{}", thread, scope);
+                        let css = format!(".{} {{}}", id);
+                        write_to(path, &id, &id, &css, &[]).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(bundle.read().lines().count(), 80);
+    }
+}
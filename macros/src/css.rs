@@ -0,0 +1,1714 @@
+//! A small, lenient CSS parser used to scope component stylesheets.
+//!
+//! The parser only understands as much CSS as the macro needs: it splits a
+//! stylesheet into rules and at-rules, descends into conditional group rules,
+//! and keeps declaration blocks as raw text so that anything it does not
+//! rewrite is emitted untouched.
+
+use std::fmt;
+
+/// A parsed stylesheet, in source order.
+pub(crate) struct Stylesheet {
+    pub(crate) items: Vec<Item>,
+}
+
+/// A single item of a stylesheet or of a group rule.
+pub(crate) enum Item {
+    /// A qualified rule such as `.foo, .bar { color: red; }`, with the byte
+    /// offset of its selector in the parsed source.
+    Rule {
+        selector: String,
+        body: String,
+        offset: usize,
+    },
+    /// A group rule such as `@media (...) { ... }` or `@layer base { ... }`,
+    /// whose nested rules are scoped like top-level ones.
+    Group { prelude: String, items: Vec<Item> },
+    /// Any other at-rule, kept verbatim. This covers statements such as
+    /// `@import "x.css";` as well as global definitions like `@keyframes` and
+    /// `@font-face`, which would be meaningless behind a scope selector.
+    AtRule { text: String, offset: usize },
+    /// A `/* ... */` comment between rules.
+    Comment(String),
+}
+
+/// At-rules whose block contains rules that apply to the component, in their
+/// block form for `@layer`.
+const GROUP_RULES: &[&str] = &[
+    "media",
+    "supports",
+    "container",
+    "layer",
+    "scope",
+    "starting-style",
+];
+
+/// At-rules whose blocks hold descriptors, keyframes or page margins rather
+/// than style rules, and are kept verbatim. Any other at-rule with rules in
+/// its block is rejected, as they would apply to the whole page.
+const VERBATIM_RULES: &[&str] = &[
+    "keyframes",
+    "font-face",
+    "page",
+    "property",
+    "counter-style",
+    "font-feature-values",
+    "font-palette-values",
+    "view-transition",
+    "position-try",
+    "color-profile",
+];
+
+/// An error produced while parsing a stylesheet.
+#[derive(Debug)]
+pub(crate) struct ParseError {
+    /// Byte offset of the problem in the parsed source.
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+impl ParseError {
+    fn at(offset: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the 1-based line and column of the error within `src`.
+    pub(crate) fn line_column(&self, src: &str) -> (usize, usize) {
+        let before = &src[..self.offset.min(src.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+            + 1;
+        (line, column)
+    }
+}
+
+impl ParseError {
+    /// Renders the error for a compile-time diagnostic about the CSS `src`,
+    /// which came from `origin`.
+    pub(crate) fn describe(&self, src: &str, origin: &str) -> String {
+        let (line, column) = self.line_column(src);
+        format!(
+            "invalid CSS in {} at line {}, column {}: {}",
+            origin, line, column, self.message
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Stylesheet {
+    /// Parses `css` into a list of top-level items.
+    pub(crate) fn parse(css: &str) -> Result<Self, ParseError> {
+        let mut cursor = Cursor { src: css, pos: 0 };
+        let items = parse_items(&mut cursor, None)?;
+        Ok(Stylesheet { items })
+    }
+}
+
+/// Parses items up to the end of input or, inside a group rule whose block
+/// opened at `nested`, up to and including the `}` closing it.
+fn parse_items(cursor: &mut Cursor<'_>, nested: Option<usize>) -> Result<Vec<Item>, ParseError> {
+    let css = cursor.src;
+    let mut items = Vec::new();
+
+    loop {
+        cursor.skip_whitespace();
+        if cursor.eof() {
+            if let Some(open) = nested {
+                return Err(ParseError::at(open, "unclosed `{`"));
+            }
+            break;
+        }
+        if cursor.starts_with("/*") {
+            let start = cursor.pos;
+            cursor.skip_comment()?;
+            items.push(Item::Comment(css[start..cursor.pos].to_string()));
+            continue;
+        }
+        if cursor.starts_with("}") {
+            if nested.is_some() {
+                cursor.pos += 1;
+                break;
+            }
+            return Err(cursor.error("unexpected `}`"));
+        }
+
+        let start = cursor.pos;
+        let prelude_end = cursor.skip_prelude()?;
+        let prelude = css[start..prelude_end].trim();
+
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            let name = at_rule
+                .split(|c: char| c.is_whitespace() || c == '(')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            if cursor.starts_with("{") && GROUP_RULES.contains(&name.as_str()) {
+                let open = cursor.pos;
+                cursor.pos += 1;
+                items.push(Item::Group {
+                    prelude: normalize_whitespace(prelude),
+                    items: parse_items(cursor, Some(open))?,
+                });
+                continue;
+            }
+            if cursor.starts_with("{") {
+                let open = cursor.pos;
+                cursor.skip_block()?;
+                let unprefixed = match name.strip_prefix('-') {
+                    Some(vendor) => vendor.split_once('-').map_or(vendor, |(_, name)| name),
+                    None => &name,
+                };
+                if !VERBATIM_RULES.contains(&unprefixed)
+                    && contains_block(&css[open + 1..cursor.pos - 1])
+                {
+                    return Err(ParseError::at(
+                        start,
+                        format!(
+                            "the rules of `@{}` cannot be scoped, and would apply to the whole page",
+                            name
+                        ),
+                    ));
+                }
+            } else if cursor.starts_with(";") {
+                cursor.pos += 1;
+            }
+            items.push(Item::AtRule {
+                text: css[start..cursor.pos].trim_end().to_string(),
+                offset: start,
+            });
+            continue;
+        }
+
+        if !cursor.starts_with("{") {
+            return Err(ParseError::at(
+                start,
+                format!("expected `{{` after `{prelude}`"),
+            ));
+        }
+        if prelude.is_empty() {
+            return Err(cursor.error("expected a selector before `{`"));
+        }
+        let body_start = cursor.pos + 1;
+        cursor.skip_block()?;
+        items.push(Item::Rule {
+            selector: prelude.to_string(),
+            body: css[body_start..cursor.pos - 1].to_string(),
+            offset: start,
+        });
+    }
+
+    Ok(items)
+}
+
+/// Whether `text` has a `{` outside of strings and comments.
+fn contains_block(text: &str) -> bool {
+    let mut cursor = Cursor { src: text, pos: 0 };
+    while let Some(c) = cursor.peek() {
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => continue,
+            '{' => return true,
+            _ => cursor.pos += c.len_utf8(),
+        }
+    }
+    false
+}
+
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_items(f, &self.items)
+    }
+}
+
+fn write_items(f: &mut fmt::Formatter<'_>, items: &[Item]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str("\n")?;
+        }
+        match item {
+            Item::Rule { selector, body, .. } => write!(f, "{selector} {{{body}}}")?,
+            Item::Group { prelude, items } => {
+                writeln!(f, "{prelude} {{")?;
+                write_items(f, items)?;
+                f.write_str("\n}")?;
+            }
+            Item::AtRule { text, .. } | Item::Comment(text) => f.write_str(text)?,
+        }
+    }
+    Ok(())
+}
+
+/// A top-level `@import` statement.
+pub(crate) struct Import {
+    /// Byte range of the whole statement, including its `;`.
+    pub(crate) range: std::ops::Range<usize>,
+    pub(crate) url: String,
+    /// Whatever follows the URL, such as a media query list.
+    pub(crate) conditions: String,
+}
+
+impl Import {
+    /// Whether the import points at another origin rather than a local file.
+    pub(crate) fn is_remote(&self) -> bool {
+        self.url.contains("://") || self.url.starts_with("//") || self.url.starts_with("data:")
+    }
+}
+
+/// Lists the top-level `@import` statements of `css`.
+pub(crate) fn imports(css: &str) -> Result<Vec<Import>, ParseError> {
+    let sheet = Stylesheet::parse(css)?;
+    let mut imports = Vec::new();
+    for item in sheet.items {
+        let Item::AtRule { text, offset } = item else {
+            continue;
+        };
+        let Some(rest) = text
+            .get(..7)
+            .filter(|name| name.eq_ignore_ascii_case("@import"))
+            .map(|_| text[7..].trim_end_matches(';').trim())
+        else {
+            continue;
+        };
+
+        let (url, conditions) = if let Some(quoted) = rest.strip_prefix(['"', '\'']) {
+            let quote = rest.as_bytes()[0] as char;
+            match quoted.find(quote) {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => continue,
+            }
+        } else if rest.len() > 4 && rest[..4].eq_ignore_ascii_case("url(") {
+            match rest.find(')') {
+                Some(end) => (
+                    rest[4..end].trim().trim_matches(['"', '\'']),
+                    &rest[end + 1..],
+                ),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+
+        imports.push(Import {
+            range: offset..offset + text.len(),
+            url: url.to_string(),
+            conditions: conditions.trim().to_string(),
+        });
+    }
+    Ok(imports)
+}
+
+/// How the selectors of a stylesheet are tied to a component.
+pub(crate) enum Scope {
+    /// Prefix every selector with this class: `.title` becomes
+    /// `.card1234 .title`.
+    Class(String),
+    /// Like `Class`, for a class carried by the root elements of the
+    /// component rather than by a wrapper: every selector also matches the
+    /// roots themselves, so `.title` becomes `.card1234 .title,
+    /// .title.card1234`.
+    Root(String),
+    /// Append this attribute to the last compound of every selector: `.title`
+    /// becomes `.title[data-scope-card1234]`.
+    Attribute(String),
+}
+
+/// Scopes every selector of every rule in `css`, including rules nested in
+/// `@media`, `@supports`, `@container`, `@layer`, `@scope` and
+/// `@starting-style` blocks.
+///
+/// `.foo, .bar { color: red; }` scoped to the class `card1234` becomes
+/// `.card1234 .foo, .card1234 .bar { color: red; }`. Declarations and
+/// at-rule preludes are left untouched, and selectors wrapped in
+/// `:global(...)` opt out of scoping. `:host` and `:scope` stand for the scope
+/// element itself.
+///
+/// Only selectors are rewritten, so the cascade is the one written: rules,
+/// at-rules and comments keep their order and nesting, and declaration
+/// blocks are copied byte for byte, declarations in their order. The
+/// whitespace between items and inside selectors and group preludes is
+/// normalized.
+pub(crate) fn scope_stylesheet(css: &str, scope: &Scope) -> Result<String, ParseError> {
+    let mut sheet = Stylesheet::parse(css)?;
+    scope_items(&mut sheet.items, scope)?;
+    Ok(sheet.to_string())
+}
+
+fn scope_items(items: &mut [Item], scope: &Scope) -> Result<(), ParseError> {
+    for item in items {
+        match item {
+            Item::Rule {
+                selector, offset, ..
+            } => {
+                if let Some(at) = empty_global(selector) {
+                    return Err(ParseError::at(*offset + at, "`:global()` needs a selector"));
+                }
+                *selector = scope_selector_list(selector, scope)
+                    .map_err(|message| ParseError::at(*offset, message))?
+            }
+            Item::Group { items, .. } => scope_items(items, scope)?,
+            Item::AtRule { .. } | Item::Comment(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Scopes each selector of a comma-separated selector list.
+fn scope_selector_list(list: &str, scope: &Scope) -> Result<String, String> {
+    split_top_level(list, ',')
+        .into_iter()
+        .map(|selector| {
+            let selector = normalize_whitespace(selector);
+            match scope {
+                Scope::Class(class) => Ok(prefix_selector(&selector, &format!(".{class}"))),
+                Scope::Root(class) => Ok(root_selector(&selector, &format!(".{class}"))),
+                Scope::Attribute(name) => {
+                    if host_compound(&selector).is_some() {
+                        return Err(format!(
+                            "`{}` needs a scope element, which the attribute strategy does not have; use the class strategy",
+                            selector
+                        ));
+                    }
+                    Ok(suffix_selector(&selector, &format!("[{name}]")))
+                }
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|selectors| selectors.join(", "))
+}
+
+/// Lists the class names used in the selectors of `css`, in order of first
+/// appearance. Classes only mentioned in attribute selectors or strings, such
+/// as `[class~="x"]`, are not included.
+pub(crate) fn class_names(css: &str) -> Result<Vec<String>, ParseError> {
+    fn collect(items: &[Item], names: &mut Vec<String>) {
+        for item in items {
+            match item {
+                Item::Rule { selector, .. } => {
+                    for name in selector_classes(selector) {
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                }
+                Item::Group { items, .. } => collect(items, names),
+                Item::AtRule { .. } | Item::Comment(_) => {}
+            }
+        }
+    }
+
+    let sheet = Stylesheet::parse(css)?;
+    let mut names = Vec::new();
+    collect(&sheet.items, &mut names);
+    Ok(names)
+}
+
+fn selector_classes(selector: &str) -> Vec<String> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let mut classes = Vec::new();
+    let mut cursor = Cursor {
+        src: selector,
+        pos: 0,
+    };
+    let mut brackets = 0usize;
+
+    while let Some(c) = cursor.peek() {
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => continue,
+            '[' => brackets += 1,
+            ']' => brackets = brackets.saturating_sub(1),
+            '.' if brackets == 0 => {
+                let rest = &selector[cursor.pos + 1..];
+                let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+                let name = &rest[..len];
+                // Escaped names such as `.sm\:flex` cannot become constants.
+                let escaped = rest[len..].starts_with('\\');
+                if !name.is_empty() && !name.starts_with(|c: char| c.is_ascii_digit()) && !escaped {
+                    classes.push(name.to_string());
+                }
+                cursor.pos += 1 + len;
+                continue;
+            }
+            _ => {}
+        }
+        cursor.pos += c.len_utf8();
+    }
+    classes
+}
+
+/// Rewrites nested rules as top-level rules, the way a preprocessor would.
+///
+/// `.foo { color: red; &:hover { color: blue; } .bar {} }` becomes
+/// `.foo { color: red; }`, `.foo:hover { color: blue; }` and `.foo .bar {}`.
+/// A `&` in a nested selector stands for the parent selector, and a nested
+/// selector without one is a descendant of it. Conditional group rules nested
+/// in a rule, such as `@media`, are hoisted around it. Declarations are kept
+/// in the parent rule, ahead of the rules nested in it.
+pub(crate) fn flatten_nesting(css: &str) -> Result<String, ParseError> {
+    let mut sheet = Stylesheet::parse(css)?;
+    sheet.items = flatten_items(css, std::mem::take(&mut sheet.items));
+    Ok(sheet.to_string())
+}
+
+/// Lists the offsets in `css` of the rules each rule of its scoped output comes
+/// from, in output order. With `flatten`, nested rules are flattened first, as
+/// [`flatten_nesting`] does.
+#[cfg(feature = "sourcemap")]
+pub(crate) fn rule_offsets(css: &str, flatten: bool) -> Result<Vec<usize>, ParseError> {
+    fn collect(items: &[Item], offsets: &mut Vec<usize>) {
+        for item in items {
+            match item {
+                Item::Rule { offset, .. } => offsets.push(*offset),
+                Item::Group { items, .. } => collect(items, offsets),
+                Item::AtRule { .. } | Item::Comment(_) => {}
+            }
+        }
+    }
+
+    let mut items = Stylesheet::parse(css)?.items;
+    if flatten {
+        items = flatten_items(css, items);
+    }
+    let mut offsets = Vec::new();
+    collect(&items, &mut offsets);
+    Ok(offsets)
+}
+
+/// Lists the selectors of `css` starting with `html`, `body` or `:root`, with
+/// the offsets of their rules. Scoped under a class, they would have to match
+/// inside the scope element, which they never do. With `flatten`, nested
+/// rules are flattened first, as [`flatten_nesting`] does.
+pub(crate) fn page_selectors(css: &str, flatten: bool) -> Result<Vec<(usize, String)>, ParseError> {
+    fn collect(items: &[Item], found: &mut Vec<(usize, String)>) {
+        for item in items {
+            match item {
+                Item::Rule {
+                    selector, offset, ..
+                } => {
+                    for selector in split_top_level(selector, ',') {
+                        let selector = normalize_whitespace(selector);
+                        if is_page_selector(&selector) {
+                            found.push((*offset, selector));
+                        }
+                    }
+                }
+                Item::Group { items, .. } => collect(items, found),
+                Item::AtRule { .. } | Item::Comment(_) => {}
+            }
+        }
+    }
+
+    let mut items = Stylesheet::parse(css)?.items;
+    if flatten {
+        items = flatten_items(css, items);
+    }
+    let mut found = Vec::new();
+    collect(&items, &mut found);
+    Ok(found)
+}
+
+fn is_page_selector(selector: &str) -> bool {
+    if host_compound(selector).is_some() {
+        return false;
+    }
+    let first = &selector[..compound_end(selector)];
+    if let Some(rest) = first.strip_prefix(":root") {
+        return !rest.starts_with(is_ident_char);
+    }
+    let name_end = first.find(|c| !is_ident_char(c)).unwrap_or(first.len());
+    ["html", "body"]
+        .iter()
+        .any(|name| first[..name_end].eq_ignore_ascii_case(name))
+}
+
+fn flatten_items(css: &str, items: Vec<Item>) -> Vec<Item> {
+    let mut out = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Item::Rule {
+                selector,
+                body,
+                offset,
+            } => {
+                let selectors = split_top_level(&selector, ',')
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                let mut cursor = Cursor {
+                    src: css,
+                    pos: offset,
+                };
+                let body_offset = cursor.skip_prelude().map_or(offset, |open| open + 1);
+                flatten_rule(&selectors, offset, &body, body_offset, &mut out);
+            }
+            Item::Group { prelude, items } => out.push(Item::Group {
+                prelude,
+                items: flatten_items(css, items),
+            }),
+            item => out.push(item),
+        }
+    }
+    out
+}
+
+/// Appends the rule `selectors { body }` to `out`, followed by the rules
+/// nested in `body`. The rules keep the offsets of the source rules they come
+/// from, `offset` for the declarations of `body`, which starts at
+/// `body_offset`.
+fn flatten_rule(
+    selectors: &[String],
+    offset: usize,
+    body: &str,
+    body_offset: usize,
+    out: &mut Vec<Item>,
+) {
+    let mut declarations = String::new();
+    let mut nested = Vec::new();
+    let mut cursor = Cursor { src: body, pos: 0 };
+
+    while !cursor.eof() {
+        let start = cursor.pos;
+        let Ok(prelude_end) = cursor.skip_prelude() else {
+            // Leave anything this cannot make sense of to the browser.
+            declarations.push_str(&body[start..]);
+            break;
+        };
+        if !cursor.starts_with("{") {
+            cursor.pos = (cursor.pos + 1).min(body.len());
+            declarations.push_str(&body[start..cursor.pos]);
+            continue;
+        }
+        let open = cursor.pos;
+        if cursor.skip_block().is_err() {
+            declarations.push_str(&body[start..]);
+            break;
+        }
+        let prelude = body[start..prelude_end].trim();
+        let prelude_offset =
+            body_offset + prelude_end - body[start..prelude_end].trim_start().len();
+        let inner = (&body[open + 1..cursor.pos - 1], body_offset + open + 1);
+        let is_group = prelude.strip_prefix('@').is_some_and(|at_rule| {
+            let name = at_rule
+                .split(|c: char| c.is_whitespace() || c == '(')
+                .next()
+                .unwrap_or_default();
+            GROUP_RULES.contains(&name.to_ascii_lowercase().as_str())
+        });
+        if prelude.starts_with('@') && !is_group {
+            declarations.push_str(&body[start..cursor.pos]);
+        } else {
+            nested.push((is_group, prelude, prelude_offset, inner));
+        }
+    }
+
+    let declarations = declarations.trim();
+    if !declarations.is_empty() || nested.is_empty() {
+        out.push(Item::Rule {
+            selector: selectors.join(", "),
+            body: if declarations.is_empty() {
+                String::new()
+            } else {
+                format!(" {} ", declarations)
+            },
+            offset,
+        });
+    }
+    for (is_group, prelude, prelude_offset, (inner, inner_offset)) in nested {
+        if is_group {
+            let mut items = Vec::new();
+            flatten_rule(selectors, prelude_offset, inner, inner_offset, &mut items);
+            out.push(Item::Group {
+                prelude: normalize_whitespace(prelude),
+                items,
+            });
+        } else {
+            let selectors = nested_selectors(selectors, prelude);
+            flatten_rule(&selectors, prelude_offset, inner, inner_offset, out);
+        }
+    }
+}
+
+/// Resolves a nested selector list against the selectors of its parent rule.
+fn nested_selectors(parents: &[String], nested: &str) -> Vec<String> {
+    let mut selectors = Vec::new();
+    for parent in parents {
+        for selector in split_top_level(nested, ',') {
+            selectors.push(if selector.contains('&') {
+                replace_nesting(selector, parent)
+            } else {
+                format!("{} {}", parent, selector)
+            });
+        }
+    }
+    selectors
+}
+
+/// Replaces every `&` of `selector` outside of strings with `parent`.
+fn replace_nesting(selector: &str, parent: &str) -> String {
+    let mut out = String::with_capacity(selector.len() + parent.len());
+    let mut cursor = Cursor {
+        src: selector,
+        pos: 0,
+    };
+    let mut last = 0;
+    while let Some(c) = cursor.peek() {
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => continue,
+            '&' => {
+                out.push_str(&selector[last..cursor.pos]);
+                out.push_str(parent);
+                last = cursor.pos + 1;
+            }
+            _ => {}
+        }
+        cursor.pos += c.len_utf8();
+    }
+    out.push_str(&selector[last..]);
+    out
+}
+
+/// Names a stylesheet defines for itself, that can be renamed so they do not
+/// clash with those of other components.
+#[derive(Default)]
+pub(crate) struct Locals {
+    /// Names of `@keyframes`.
+    pub(crate) keyframes: Vec<String>,
+    /// Custom properties given a value in a declaration, such as `--gap`.
+    pub(crate) properties: Vec<String>,
+}
+
+impl Locals {
+    /// Records the keyframes (with `keyframes`) and custom properties (with
+    /// `properties`) defined by `css`.
+    pub(crate) fn collect(
+        &mut self,
+        css: &str,
+        keyframes: bool,
+        properties: bool,
+    ) -> Result<(), ParseError> {
+        fn collect_items(items: &[Item], locals: &mut Locals, keyframes: bool, properties: bool) {
+            for item in items {
+                let text = match item {
+                    Item::Rule { body, .. } => body,
+                    Item::AtRule { text, .. } => {
+                        if let Some((_, name)) = keyframes_name(text).filter(|_| keyframes) {
+                            push_new(&mut locals.keyframes, name);
+                        }
+                        text
+                    }
+                    Item::Group { items, .. } => {
+                        collect_items(items, locals, keyframes, properties);
+                        continue;
+                    }
+                    Item::Comment(_) => continue,
+                };
+                if properties {
+                    visit_idents(text, |ident| {
+                        if ident.is_property && ident.name.starts_with("--") {
+                            push_new(&mut locals.properties, ident.name);
+                        }
+                        None
+                    });
+                }
+            }
+        }
+
+        let sheet = Stylesheet::parse(css)?;
+        collect_items(&sheet.items, self, keyframes, properties);
+        Ok(())
+    }
+}
+
+fn push_new(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|existing| existing == name) {
+        names.push(name.to_string());
+    }
+}
+
+/// Appends `suffix` to every use of the `locals` of `css`: the `@keyframes`
+/// names themselves and where `animation` and `animation-name` refer to them,
+/// and the custom properties both where they are declared and where `var()`
+/// reads them. Selectors are left alone.
+pub(crate) fn rename_locals(
+    css: &str,
+    locals: &Locals,
+    suffix: &str,
+) -> Result<String, ParseError> {
+    fn rename_items(items: &mut [Item], locals: &Locals, suffix: &str) {
+        for item in items {
+            match item {
+                Item::Rule { body, .. } => *body = rename_in(body, locals, suffix),
+                Item::AtRule { text, .. } => {
+                    if let Some((range, name)) = keyframes_name(text) {
+                        if locals.keyframes.iter().any(|local| local == name) {
+                            let renamed = format!("{name}{suffix}");
+                            text.replace_range(range, &renamed);
+                        }
+                    }
+                    *text = rename_in(text, locals, suffix);
+                }
+                Item::Group { items, .. } => rename_items(items, locals, suffix),
+                Item::Comment(_) => {}
+            }
+        }
+    }
+
+    let mut sheet = Stylesheet::parse(css)?;
+    rename_items(&mut sheet.items, locals, suffix);
+    Ok(sheet.to_string())
+}
+
+fn rename_in(text: &str, locals: &Locals, suffix: &str) -> String {
+    let is_local = |names: &[String], name: &str| names.iter().any(|local| local == name);
+    visit_idents(text, |ident| {
+        let in_animation = ident.property.is_some_and(|property| {
+            let property = property.to_ascii_lowercase();
+            let property = property.strip_prefix("-webkit-").unwrap_or(&property);
+            property == "animation" || property == "animation-name"
+        });
+        let renamed = (ident.name.starts_with("--") && is_local(&locals.properties, ident.name))
+            || (in_animation && !ident.is_property && is_local(&locals.keyframes, ident.name));
+        renamed.then(|| format!("{}{}", ident.name, suffix))
+    })
+}
+
+/// Returns the name of a `@keyframes` rule, with its byte range in `text`.
+fn keyframes_name(text: &str) -> Option<(std::ops::Range<usize>, &str)> {
+    let keyword_end = text.find(|c: char| c.is_whitespace())?;
+    let keyword = text.strip_prefix('@')?[..keyword_end - 1].to_ascii_lowercase();
+    let unprefixed = ["-webkit-", "-moz-", "-o-"]
+        .iter()
+        .find_map(|prefix| keyword.strip_prefix(prefix))
+        .unwrap_or(&keyword);
+    if unprefixed != "keyframes" {
+        return None;
+    }
+    let start = keyword_end + (text[keyword_end..].len() - text[keyword_end..].trim_start().len());
+    let len = text[start..]
+        .find(|c: char| !is_ident_char(c))
+        .unwrap_or(text.len() - start);
+    (len > 0).then(|| (start..start + len, &text[start..start + len]))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+/// An identifier found by [`visit_idents`].
+struct IdentToken<'a> {
+    name: &'a str,
+    /// The property of the declaration the identifier is part of, if known.
+    property: Option<&'a str>,
+    /// Whether the identifier is that property's name.
+    is_property: bool,
+}
+
+/// Calls `visit` with every identifier of a block of declarations outside of
+/// strings and comments, and replaces those it returns a new name for.
+fn visit_idents(text: &str, mut visit: impl FnMut(IdentToken<'_>) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = Cursor { src: text, pos: 0 };
+    let mut property = None;
+    let mut declaration_start = true;
+    let mut last = 0;
+
+    while let Some(c) = cursor.peek() {
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => continue,
+            ';' | '{' | '}' => {
+                property = None;
+                declaration_start = true;
+            }
+            c if is_ident_char(c) => {
+                let start = cursor.pos;
+                let len = text[start..]
+                    .find(|c: char| !is_ident_char(c))
+                    .unwrap_or(text.len() - start);
+                let name = &text[start..start + len];
+                cursor.pos += len;
+
+                let is_property =
+                    declaration_start && text[cursor.pos..].trim_start().starts_with(':');
+                if is_property {
+                    property = Some(name);
+                }
+                declaration_start = false;
+                if let Some(renamed) = visit(IdentToken {
+                    name,
+                    property,
+                    is_property,
+                }) {
+                    out.push_str(&text[last..start]);
+                    out.push_str(&renamed);
+                    last = cursor.pos;
+                }
+                continue;
+            }
+            c if !c.is_whitespace() => declaration_start = false,
+            _ => {}
+        }
+        cursor.pos += c.len_utf8();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+const GLOBAL: &str = ":global(";
+
+/// Prefixes a single selector with `scope`, honouring `:global(...)`.
+///
+/// A leading `:global(...)` compound stays outside the scope, so
+/// `:global(body)` becomes `body` and `:global(.modal) .title` becomes
+/// `.modal .card1234 .title`. Anywhere else, `:global(...)` is simply
+/// unwrapped.
+fn prefix_selector(selector: &str, scope: &str) -> String {
+    if let Some((start, end)) = host_compound(selector) {
+        return host_selector(selector, start, end, scope);
+    }
+    match leading_global(selector) {
+        Some((global, "")) => global,
+        Some((global, rest)) => format!("{global} {scope} {}", unwrap_globals(rest)),
+        None => format!("{scope} {}", unwrap_globals(selector)),
+    }
+}
+
+/// Scopes a single selector to root elements carrying `scope`, so that it
+/// matches the roots as well as their descendants: `.title` becomes
+/// `.card1234 .title, .title.card1234`, and `.a:hover .b::before` also gives
+/// `.a:hover.card1234 .b::before`. `:global(...)` and `:host` are handled as
+/// by [`prefix_selector`].
+fn root_selector(selector: &str, scope: &str) -> String {
+    if let Some((start, end)) = host_compound(selector) {
+        return host_selector(selector, start, end, scope);
+    }
+    let (global, rest) = match leading_global(selector) {
+        Some((global, "")) => return global,
+        Some((global, rest)) => (format!("{global} "), unwrap_globals(rest)),
+        None => (String::new(), unwrap_globals(selector)),
+    };
+    let Some(&(start, end)) = compounds(&rest).first() else {
+        return format!("{global}{rest}");
+    };
+    let compound = &rest[start..end];
+    let insert = start + pseudo_element_start(compound).unwrap_or(compound.len());
+    format!(
+        "{global}{scope} {rest}, {global}{}{scope}{}",
+        &rest[..insert],
+        &rest[insert..]
+    )
+}
+
+/// Splits a leading `:global(...)` compound off `selector`, returning it
+/// unwrapped along with the rest of the selector.
+fn leading_global(selector: &str) -> Option<(String, &str)> {
+    let inner = selector.strip_prefix(GLOBAL)?;
+    let close = closing_paren(inner)?;
+    // The global part extends to the end of its compound selector, so
+    // `:global(.modal):hover .title` keeps `:hover` on `.modal`.
+    let end = close + compound_end(&inner[close..]);
+    let global = unwrap_globals(&format!("{}{}", &inner[..close], &inner[close + 1..end]));
+    Some((global, inner[end..].trim()))
+}
+
+/// Pseudo-classes standing for the scope element, as in a shadow root.
+const HOST: &[&str] = &[":host", ":scope"];
+
+/// Returns the byte range of the first compound of `selector` starting with
+/// `:host` or `:scope`.
+fn host_compound(selector: &str) -> Option<(usize, usize)> {
+    compounds(selector).into_iter().find(|&(start, end)| {
+        let compound = &selector[start..end];
+        HOST.iter().any(|host| {
+            compound
+                .strip_prefix(host)
+                .is_some_and(|rest| !rest.starts_with(is_ident_char))
+        })
+    })
+}
+
+/// Replaces the `:host` compound of `selector`, at `start..end`, with `scope`:
+/// `:host` becomes `.card1234`, `:host(:hover)` and `:host:hover` become
+/// `.card1234:hover`, and `:host .title` becomes `.card1234 .title`. A
+/// selector before it is left unscoped, so `.dark :host` matches a scope
+/// element inside `.dark`.
+fn host_selector(selector: &str, start: usize, end: usize, scope: &str) -> String {
+    let compound = &selector[start..end];
+    let rest = HOST
+        .iter()
+        .find_map(|host| compound.strip_prefix(host))
+        .unwrap_or_default();
+    let host = match rest
+        .strip_prefix('(')
+        .and_then(|args| closing_paren(args).map(|close| (&args[..close], &args[close + 1..])))
+    {
+        Some((args, tail)) => format!("{scope}{}{tail}", args.trim()),
+        None => format!("{scope}{rest}"),
+    };
+    format!(
+        "{}{host}{}",
+        unwrap_globals(&selector[..start]),
+        unwrap_globals(&selector[end..])
+    )
+}
+
+/// Appends `attribute` to the last compound of `selector` that is not wrapped
+/// in `:global(...)`, before any pseudo-element: `.a .b::before` becomes
+/// `.a .b[data-scope-card1234]::before`.
+fn suffix_selector(selector: &str, attribute: &str) -> String {
+    let target = compounds(selector)
+        .into_iter()
+        .rev()
+        .find(|&(start, _)| !selector[start..].starts_with(GLOBAL));
+    let Some((start, end)) = target else {
+        return unwrap_globals(selector);
+    };
+    let compound = &selector[start..end];
+    let insert = start + pseudo_element_start(compound).unwrap_or(compound.len());
+    unwrap_globals(&format!(
+        "{}{}{}",
+        &selector[..insert],
+        attribute,
+        &selector[insert..]
+    ))
+}
+
+/// Returns the byte ranges of the compound selectors making up `selector`.
+fn compounds(selector: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ' ' | '>' | '+' | '~' if depth == 0 => {
+                if let Some(start) = start.take() {
+                    ranges.push((start, i));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        ranges.push((start, selector.len()));
+    }
+    ranges
+}
+
+/// Returns the offset of the pseudo-element ending `compound`, if any.
+fn pseudo_element_start(compound: &str) -> Option<usize> {
+    const LEGACY: &[&str] = &[":before", ":after", ":first-line", ":first-letter"];
+    let mut depth = 0usize;
+    for (i, c) in compound.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => {
+                let rest = &compound[i..];
+                if rest.starts_with("::") || LEGACY.iter().any(|legacy| rest.starts_with(legacy)) {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the offset of the first `:global()` of `selector` with nothing
+/// in it, which would leave an empty selector once unwrapped.
+fn empty_global(selector: &str) -> Option<usize> {
+    selector.match_indices(GLOBAL).find_map(|(start, _)| {
+        let inner = &selector[start + GLOBAL.len()..];
+        closing_paren(inner)
+            .filter(|&close| inner[..close].trim().is_empty())
+            .map(|_| start)
+    })
+}
+
+/// Replaces every `:global(x)` in `selector` with `x`.
+fn unwrap_globals(selector: &str) -> String {
+    let mut out = String::with_capacity(selector.len());
+    let mut rest = selector;
+    while let Some(start) = rest.find(GLOBAL) {
+        let inner = &rest[start + GLOBAL.len()..];
+        let Some(close) = closing_paren(inner) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&unwrap_globals(&inner[..close]));
+        rest = &inner[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Returns the offset of the `)` closing a parenthesis opened just before `s`.
+fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 1usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the length of the compound selector at the start of `s`, i.e. the
+/// offset of the first combinator outside of parentheses.
+fn compound_end(s: &str) -> usize {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ' ' | '>' | '+' | '~' if depth == 0 => return i,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Collapses runs of whitespace in a selector into single spaces.
+fn normalize_whitespace(selector: &str) -> String {
+    selector.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Removes the rules of `css` that appear again later in the same block, with
+/// the same selector and the same declarations, character for character but
+/// for the whitespace around them.
+///
+/// The last copy is the one kept: it is the copy that wins the cascade, so
+/// removing the earlier ones cannot change which declaration applies. Rules
+/// that differ in any way, even in whitespace, are all kept in their order.
+pub(crate) fn dedupe_rules(css: &str) -> String {
+    fn dedupe_items(items: &mut Vec<Item>) {
+        let mut i = 0;
+        while i < items.len() {
+            let repeated = match &items[i] {
+                Item::Rule { selector, body, .. } => items[i + 1..].iter().any(|later| {
+                    matches!(later, Item::Rule { selector: s, body: b, .. }
+                        if s.trim() == selector.trim() && b.trim() == body.trim())
+                }),
+                _ => false,
+            };
+            if repeated {
+                items.remove(i);
+                continue;
+            }
+            if let Item::Group { items, .. } = &mut items[i] {
+                dedupe_items(items);
+            }
+            i += 1;
+        }
+    }
+
+    match Stylesheet::parse(css) {
+        Ok(mut sheet) => {
+            dedupe_items(&mut sheet.items);
+            sheet.to_string()
+        }
+        Err(_) => css.to_string(),
+    }
+}
+
+/// A section of a stylesheet delimited by `/* @theme <name> */` and
+/// `/* @endtheme */` comments.
+pub(crate) struct ThemeSection {
+    pub(crate) name: String,
+    /// Byte range of the section, markers included.
+    pub(crate) range: std::ops::Range<usize>,
+}
+
+/// Lists the theme sections of `css`, in order. A section cannot contain
+/// another one.
+pub(crate) fn theme_sections(css: &str) -> Result<Vec<ThemeSection>, ParseError> {
+    let mut sections = Vec::new();
+    let mut open: Option<(String, usize)> = None;
+    let mut cursor = Cursor { src: css, pos: 0 };
+
+    while let Some(c) = cursor.peek() {
+        let start = cursor.pos;
+        if c == '/' && cursor.starts_with("/*") {
+            cursor.skip_comment()?;
+            let text = css[start + 2..cursor.pos - 2].trim();
+            if let Some(name) = text.strip_prefix("@theme") {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(ParseError::at(
+                        start,
+                        "expected a theme name after `@theme`",
+                    ));
+                }
+                if let Some((outer, _)) = &open {
+                    return Err(ParseError::at(
+                        start,
+                        format!("theme `{name}` starts inside theme `{outer}`"),
+                    ));
+                }
+                open = Some((name.to_string(), start));
+            } else if text == "@endtheme" {
+                let Some((name, section_start)) = open.take() else {
+                    return Err(ParseError::at(start, "`@endtheme` without `@theme`"));
+                };
+                sections.push(ThemeSection {
+                    name,
+                    range: section_start..cursor.pos,
+                });
+            }
+            continue;
+        }
+        if matches!(c, '"' | '\'') {
+            cursor.skip_string()?;
+            continue;
+        }
+        cursor.pos += c.len_utf8();
+    }
+    match open {
+        Some((name, start)) => Err(ParseError::at(
+            start,
+            format!("theme `{name}` is never closed by `/* @endtheme */`"),
+        )),
+        None => Ok(sections),
+    }
+}
+
+/// Keeps the sections of `theme` and the CSS outside of any section, blanking
+/// out the sections of other themes. Line breaks are kept, so positions in
+/// the result are the same as in `css`.
+pub(crate) fn select_theme(css: &str, sections: &[ThemeSection], theme: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut last = 0;
+    for section in sections.iter().filter(|section| section.name != theme) {
+        out.push_str(&css[last..section.range.start]);
+        out.extend(
+            css[section.range.clone()]
+                .chars()
+                .map(|c| if c == '\n' { c } else { ' ' }),
+        );
+        last = section.range.end;
+    }
+    out.push_str(&css[last..]);
+    out
+}
+
+/// Whether `text` ends with `suffix`, ignoring ASCII case. Only the end is
+/// compared, as this runs on every `(` of a stylesheet.
+pub(crate) fn ends_with_ignore_case(text: &str, suffix: &str) -> bool {
+    text.len() >= suffix.len()
+        && text.as_bytes()[text.len() - suffix.len()..].eq_ignore_ascii_case(suffix.as_bytes())
+}
+
+/// Removes the `/* ... */` comments of `css`, along with the lines they leave
+/// empty. Strings and unquoted `url(...)` arguments are copied as is, and a
+/// comment between two names or numbers (`0/**/1px`) is replaced by a space so
+/// they stay apart.
+pub(crate) fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut cursor = Cursor { src: css, pos: 0 };
+    // Whether a comment was removed from the line being written.
+    let mut stripped = false;
+
+    while let Some(c) = cursor.peek() {
+        let start = cursor.pos;
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => {
+                if c != '/' {
+                    out.push_str(&css[start..cursor.pos]);
+                    continue;
+                }
+                let name = |c: Option<char>| c.is_some_and(is_ident_char);
+                if name(out.chars().last()) && name(cursor.peek()) {
+                    out.push(' ');
+                }
+                stripped = true;
+                continue;
+            }
+            '(' if ends_with_ignore_case(&out, "url") => {
+                let rest = css[start + 1..].trim_start();
+                if !rest.starts_with(['"', '\'']) {
+                    let end = css[start..]
+                        .find(')')
+                        .map_or(css.len(), |end| start + end + 1);
+                    out.push_str(&css[start..end]);
+                    cursor.pos = end;
+                    continue;
+                }
+            }
+            '\n' if stripped => {
+                stripped = false;
+                let line_start = out.rfind('\n').map_or(0, |newline| newline + 1);
+                if out[line_start..].trim().is_empty() {
+                    out.truncate(line_start);
+                    cursor.pos += 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(c);
+        cursor.pos += c.len_utf8();
+    }
+    if stripped {
+        out.truncate(out.trim_end().len());
+    }
+    out
+}
+
+/// Splits `s` on `separator`, ignoring separators nested in parentheses,
+/// brackets, strings or comments.
+pub(crate) fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut cursor = Cursor { src: s, pos: 0 };
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    while let Some(c) = cursor.peek() {
+        match c {
+            '"' | '\'' | '/' if cursor.skip_string_or_comment() => continue,
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                parts.push(s[start..cursor.pos].trim());
+                start = cursor.pos + c.len_utf8();
+            }
+            _ => {}
+        }
+        cursor.pos += c.len_utf8();
+    }
+    parts.push(s[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// A byte-offset cursor over CSS source.
+struct Cursor<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn eof(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn starts_with(&self, pattern: &str) -> bool {
+        self.src[self.pos..].starts_with(pattern)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::at(self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Skips a `/* ... */` comment starting at the cursor.
+    fn skip_comment(&mut self) -> Result<(), ParseError> {
+        match self.src[self.pos + 2..].find("*/") {
+            Some(end) => {
+                self.pos += 2 + end + 2;
+                Ok(())
+            }
+            None => Err(self.error("unterminated comment")),
+        }
+    }
+
+    /// Skips a quoted string starting at the cursor, honouring escapes.
+    fn skip_string(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => {
+                    if let Some(escaped) = self.peek() {
+                        self.pos += escaped.len_utf8();
+                    }
+                }
+                c if c == quote => return Ok(()),
+                '\n' => break,
+                _ => {}
+            }
+        }
+        Err(ParseError::at(start, "unterminated string"))
+    }
+
+    /// Skips a string or comment if one starts at the cursor, returning
+    /// whether anything was skipped. Unterminated input is skipped to the end.
+    fn skip_string_or_comment(&mut self) -> bool {
+        let skipped = if self.starts_with("/*") {
+            self.skip_comment()
+        } else if self.starts_with("\"") || self.starts_with("'") {
+            self.skip_string()
+        } else {
+            return false;
+        };
+        if skipped.is_err() {
+            self.pos = self.src.len();
+        }
+        true
+    }
+
+    /// Advances to the `{` or `;` that ends a rule prelude, returning the
+    /// offset where the prelude ends. The cursor is left on the terminator.
+    fn skip_prelude(&mut self) -> Result<usize, ParseError> {
+        let mut open = Vec::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '"' | '\'' => {
+                    self.skip_string()?;
+                    continue;
+                }
+                '/' if self.starts_with("/*") => {
+                    self.skip_comment()?;
+                    continue;
+                }
+                '(' | '[' => open.push((c, self.pos)),
+                ')' | ']' => match open.pop() {
+                    Some(('(', _)) if c == ')' => {}
+                    Some(('[', _)) if c == ']' => {}
+                    _ => return Err(self.error(format!("unexpected `{c}`"))),
+                },
+                '{' | ';' if open.is_empty() => return Ok(self.pos),
+                '}' if open.is_empty() => return Err(self.error("unexpected `}`")),
+                _ => {}
+            }
+            self.pos += c.len_utf8();
+        }
+        match open.last() {
+            Some(&(c, offset)) => Err(ParseError::at(offset, format!("unclosed `{c}`"))),
+            None => Ok(self.pos),
+        }
+    }
+
+    /// Skips a `{ ... }` block starting at the cursor, including any nested
+    /// blocks. The cursor is left just past the closing brace.
+    fn skip_block(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' | '\'' => {
+                    self.skip_string()?;
+                    continue;
+                }
+                '/' if self.starts_with("/*") => {
+                    self.skip_comment()?;
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+            self.pos += c.len_utf8();
+        }
+        Err(ParseError::at(start, "unclosed `{`"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoped(css: &str) -> String {
+        scope_stylesheet(css, &Scope::Class("card".to_string())).unwrap()
+    }
+
+    #[test]
+    fn scopes_each_selector_of_a_rule() {
+        assert_eq!(
+            scoped(".foo, .bar { color: red; }"),
+            ".card .foo, .card .bar { color: red; }"
+        );
+        assert_eq!(
+            scoped("ul  li >\n  a,\n.nav a:hover {\n  color: red;\n}\n.x { content: \"a, b { }\"; }"),
+            ".card ul li > a, .card .nav a:hover {\n  color: red;\n}\n.card .x { content: \"a, b { }\"; }"
+        );
+        assert_eq!(
+            scoped("a[title=\"x, y\"], :is(.a, .b) p { margin: 0 }"),
+            ".card a[title=\"x, y\"], .card :is(.a, .b) p { margin: 0 }"
+        );
+    }
+
+    #[test]
+    fn strip_comments_keeps_unquoted_urls() {
+        assert_eq!(
+            strip_comments(".a { background: URL(//cdn/x.png); /* gone */ }"),
+            ".a { background: URL(//cdn/x.png);  }"
+        );
+        assert_eq!(
+            strip_comments(".é { margin: 0/**/1px }"),
+            ".é { margin: 0 1px }"
+        );
+    }
+
+    #[test]
+    fn scopes_rules_in_layer_scope_and_starting_style_blocks() {
+        let scope = Scope::Class("card".to_string());
+        assert_eq!(
+            scope_stylesheet(
+                "@layer base { .a { color: red; } }\n@layer base, theme;",
+                &scope
+            )
+            .unwrap(),
+            "@layer base {\n.card .a { color: red; }\n}\n@layer base, theme;"
+        );
+        assert_eq!(
+            scope_stylesheet(
+                "@scope (.list) to (.item) { .title { color: red; } }",
+                &scope
+            )
+            .unwrap(),
+            "@scope (.list) to (.item) {\n.card .title { color: red; }\n}"
+        );
+        assert_eq!(
+            scope_stylesheet("@starting-style { .a { opacity: 0; } }", &scope).unwrap(),
+            "@starting-style {\n.card .a { opacity: 0; }\n}"
+        );
+    }
+
+    #[test]
+    fn keeps_descriptor_blocks_and_rejects_other_blocks_of_rules() {
+        let scope = Scope::Class("card".to_string());
+        for css in [
+            "@keyframes k { from { opacity: 0; } to { opacity: 1; } }",
+            "@-webkit-keyframes k { from { opacity: 0; } }",
+            "@font-face { font-family: f; src: url(f.woff); }",
+            "@page :first { margin: 1in; @top-left { content: \"{\"; } }",
+            "@unknown-descriptors { a: b; }",
+        ] {
+            assert_eq!(scope_stylesheet(css, &scope).unwrap(), css);
+        }
+        let css = ".a { color: red; }\n@document url(x) { .a { color: blue; } }";
+        let error = scope_stylesheet(css, &scope).unwrap_err();
+        assert_eq!(
+            error.message,
+            "the rules of `@document` cannot be scoped, and would apply to the whole page"
+        );
+        assert_eq!(error.line_column(css), (2, 1));
+        assert!(scope_stylesheet("@-moz-document x { .a {} }", &scope).is_err());
+    }
+
+    #[test]
+    fn root_scopes_match_the_roots_and_their_descendants() {
+        let scope = Scope::Root("card".to_string());
+        let scoped = |selector: &str| {
+            let css = format!("{selector} {{ color: red; }}");
+            let scoped = scope_stylesheet(&css, &scope).unwrap();
+            scoped.strip_suffix(" { color: red; }").unwrap().to_string()
+        };
+        assert_eq!(scoped(".title"), ".card .title, .title.card");
+        assert_eq!(scoped("p, a"), ".card p, p.card, .card a, a.card");
+        assert_eq!(
+            scoped(".a:hover > .b::before"),
+            ".card .a:hover > .b::before, .a:hover.card > .b::before"
+        );
+        assert_eq!(scoped("li:after"), ".card li:after, li.card:after");
+        assert_eq!(
+            scoped(":global(.dark) .title"),
+            ".dark .card .title, .dark .title.card"
+        );
+        assert_eq!(scoped(":global(body)"), "body");
+        assert_eq!(scoped(":host(:hover) .title"), ".card:hover .title");
+    }
+
+    #[test]
+    fn rejects_an_empty_global() {
+        let css = ".a { color: red; }\n.b, :global( ) .c { color: blue; }";
+        let scope = Scope::Class("card".to_string());
+        let error = scope_stylesheet(css, &scope).unwrap_err();
+        assert_eq!(error.message, "`:global()` needs a selector");
+        assert_eq!(error.line_column(css), (2, 5));
+        let error = scope_stylesheet(":global() { color: red; }", &scope).unwrap_err();
+        assert_eq!(error.offset, 0);
+    }
+
+    #[test]
+    fn scopes_every_rule_of_a_media_query() {
+        assert_eq!(
+            scoped("@media (min-width: 600px) {\n  .a { color: red; }\n  .b:hover { color: blue; }\n}"),
+            "@media (min-width: 600px) {\n.card .a { color: red; }\n.card .b:hover { color: blue; }\n}"
+        );
+    }
+
+    #[test]
+    fn leaves_global_selectors_unscoped() {
+        assert_eq!(
+            scoped(":global(body) { margin: 0; }"),
+            "body { margin: 0; }"
+        );
+        assert_eq!(
+            scoped(":global(.dark) .a { color: red; }"),
+            ".dark .card .a { color: red; }"
+        );
+        assert_eq!(
+            scoped(".x :global(.y) { color: blue; }"),
+            ".card .x .y { color: blue; }"
+        );
+    }
+
+    #[test]
+    fn attribute_scopes_suffix_the_last_compound() {
+        let scope = Scope::Attribute("data-scope-card".to_string());
+        let css = ".a > .b:hover, p::before, .c .d { color: red; }";
+        assert_eq!(
+            scope_stylesheet(css, &scope).unwrap(),
+            ".a > .b:hover[data-scope-card], p[data-scope-card]::before, .c .d[data-scope-card] { color: red; }"
+        );
+    }
+
+    #[test]
+    fn renames_local_keyframes_and_custom_properties() {
+        let css = "@keyframes spin { to { transform: rotate(1turn); } }\n\
+                   .a { --gap: 4px; animation: spin 1s; margin: var(--gap); }\n\
+                   .b { animation-name: fade; padding: var(--outer, 2px); }";
+        let mut locals = Locals::default();
+        locals.collect(css, true, true).unwrap();
+        assert_eq!(locals.keyframes, ["spin"]);
+        assert_eq!(locals.properties, ["--gap"]);
+        assert_eq!(
+            rename_locals(css, &locals, "-card").unwrap(),
+            "@keyframes spin-card { to { transform: rotate(1turn); } }\n\
+             .a { --gap-card: 4px; animation: spin-card 1s; margin: var(--gap-card); }\n\
+             .b { animation-name: fade; padding: var(--outer, 2px); }"
+        );
+    }
+
+    #[test]
+    fn scopes_the_rules_of_a_named_container_query() {
+        assert_eq!(
+            scoped(
+                "@container sidebar (min-width: 400px) {\n  .a { color: red; }\n  .b { color: blue; }\n}\n\
+                 .wrap { container-type: inline-size; container-name: sidebar; }"
+            ),
+            "@container sidebar (min-width: 400px) {\n.card .a { color: red; }\n.card .b { color: blue; }\n}\n\
+             .card .wrap { container-type: inline-size; container-name: sidebar; }"
+        );
+    }
+
+    #[test]
+    fn flattens_rules_nested_two_levels_deep() {
+        assert_eq!(
+            flatten_nesting(".a {\n  color: red;\n  .b {\n    color: blue;\n    &:hover .c { color: green; }\n  }\n}")
+                .unwrap(),
+            ".a { color: red; }\n.a .b { color: blue; }\n.a .b:hover .c { color: green; }"
+        );
+    }
+
+    #[test]
+    fn scopes_dark_mode_rules_beside_the_light_ones() {
+        assert_eq!(
+            scoped(
+                ".a { color: black; }\n@media (prefers-color-scheme: dark) {\n  .a { color: white; }\n  .b:hover { color: grey; }\n}\n.b { color: blue; }"
+            ),
+            ".card .a { color: black; }\n@media (prefers-color-scheme: dark) {\n.card .a { color: white; }\n.card .b:hover { color: grey; }\n}\n.card .b { color: blue; }"
+        );
+    }
+
+    #[test]
+    fn scopes_the_rules_of_feature_queries() {
+        assert_eq!(
+            scoped(
+                "@supports (display: grid) and (not (display: inline-grid)) {\n  .a { display: grid; }\n  .b { display: grid; }\n}\n\
+                 @supports (gap: 1px) or (grid-gap: 1px) {\n  .c { gap: 1px; }\n}"
+            ),
+            "@supports (display: grid) and (not (display: inline-grid)) {\n.card .a { display: grid; }\n.card .b { display: grid; }\n}\n\
+             @supports (gap: 1px) or (grid-gap: 1px) {\n.card .c { gap: 1px; }\n}"
+        );
+    }
+
+    #[test]
+    fn strips_comments_inside_and_outside_blocks_but_not_strings() {
+        assert_eq!(
+            strip_comments(
+                "/* card */\n.a { /* inside */ color: red; content: \"/* kept */\"; }\n/* after */\n.b { margin: 0/**/1px; }"
+            ),
+            ".a {  color: red; content: \"/* kept */\"; }\n.b { margin: 0 1px; }"
+        );
+    }
+
+    #[test]
+    fn host_selectors_match_the_scope_element() {
+        assert_eq!(
+            scoped(":host { display: block; }\n:host(:hover) { color: red; }\n:host .child { color: blue; }"),
+            ".card { display: block; }\n.card:hover { color: red; }\n.card .child { color: blue; }"
+        );
+    }
+
+    #[test]
+    fn dedupes_exact_copies_only() {
+        assert_eq!(
+            dedupe_rules(".a { color: red; }\n.b { color: blue; }\n.a  {  color: red;  }\n.b { color: navy; }"),
+            ".b { color: blue; }\n.a {  color: red;  }\n.b { color: navy; }"
+        );
+    }
+
+    #[test]
+    fn selects_the_sections_of_a_theme() {
+        let css = ".a {}\n/* @theme dark */\n.b {}\n/* @endtheme */\n/* @theme light */\n.c {}\n/* @endtheme */";
+        let sections = theme_sections(css).unwrap();
+        let names: Vec<&str> = sections
+            .iter()
+            .map(|section| section.name.as_str())
+            .collect();
+        assert_eq!(names, ["dark", "light"]);
+        let dark = select_theme(css, &sections, "dark");
+        // Dropped sections keep their lines, so positions stay the same.
+        assert_eq!(dark.lines().count(), css.lines().count());
+        assert_eq!(
+            dark.split_whitespace().collect::<Vec<_>>().join(" "),
+            ".a {} /* @theme dark */ .b {} /* @endtheme */"
+        );
+    }
+
+    #[test]
+    fn rejects_unbalanced_theme_markers() {
+        let Err(unclosed) = theme_sections("/* @theme dark */ .b {}") else {
+            panic!("accepted an unclosed theme");
+        };
+        assert_eq!(
+            unclosed.message,
+            "theme `dark` is never closed by `/* @endtheme */`"
+        );
+        let Err(nested) = theme_sections("/* @theme a */ /* @theme b */") else {
+            panic!("accepted nested themes");
+        };
+        assert_eq!(
+            (nested.offset, nested.message.as_str()),
+            (15, "theme `b` starts inside theme `a`")
+        );
+    }
+}
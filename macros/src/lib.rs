@@ -0,0 +1,1756 @@
+mod args;
+#[cfg(feature = "bundle")]
+mod bundle;
+mod css;
+mod load;
+#[cfg(feature = "minify")]
+mod minify;
+#[cfg(feature = "prefix")]
+mod prefix;
+mod rtl;
+mod scopes;
+#[cfg(feature = "sourcemap")]
+mod sourcemap;
+mod transform;
+mod view;
+
+use args::{Direction, Inject, ModuleArgs, Strategy, StyledInput, StylesArgs};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Block, ItemFn};
+
+/// Applies CSS styles from a specified file to a Leptos component.
+///
+/// This attribute macro transforms a Leptos component function by:
+/// 1. Reading the content of a CSS file specified by `path`.
+/// 2. Generating a unique ID for the component from the CSS filename (stem)
+///    followed by a hash of the file's path and contents. Characters that are
+///    not valid in a CSS identifier are replaced, so `my card.css` gives
+///    `my_card1a2b3c4d` and `2col.css` gives `_2col1a2b3c4d`.
+/// 3. Wrapping the original component's view output within a `<div>` element
+///    whose `class` attribute is set to the generated unique ID.
+/// 4. Injecting a `<style>` tag containing the CSS from the file, with every
+///    selector prefixed by a class selector for the generated unique ID. On
+///    the server it is rendered by the first instance of each page, so a list
+///    of fifty cards still ships one stylesheet; in the browser it is added to
+///    the `<head>`, where it stays when that instance goes away.
+///
+/// The CSS in the file will be applied to the children of the wrapped `<div>`
+/// using the class selector. Since the scope is a class rather than an `id`, a
+/// component can be rendered any number of times on the same page. Rules
+/// nested in `@media`, `@supports`, `@container`, `@layer`, `@scope` and
+/// `@starting-style` blocks are scoped the same way, while the at-rule itself
+/// is kept as is. At-rules holding descriptors or keyframes, such as
+/// `@keyframes`, `@font-face` and `@page`, define global names and are passed
+/// through verbatim. Any other at-rule with style rules in its block, such as
+/// `@document`, is a compile error, as its rules would style the whole page.
+///
+/// As in a shadow root, `:host` (or `:scope`) selects the scope element
+/// itself rather than its descendants: `:host` becomes `.card1234`,
+/// `:host(:hover)` becomes `.card1234:hover`, and `:host .title` becomes
+/// `.card1234 .title`. The attribute strategy has no single scope element, so
+/// it rejects them.
+///
+/// A selector starting with `html`, `body` or `:root` ends up behind the
+/// scope class, inside the component, where it can never match. Such
+/// selectors are reported as compiler warnings suggesting `:global(...)`;
+/// the rest of the stylesheet is scoped as usual.
+///
+/// The scope ID is also available to Rust code as a constant next to the
+/// component, with the same visibility: `CARD_SCOPE` for `Card`. Other
+/// components can build selectors from it, and tests can look for it in
+/// rendered markup. Unscoped and empty stylesheets have none.
+///
+/// Should two different stylesheets of the same crate ever hash to the same
+/// scope ID, the second one is rejected with a compile error naming both, as
+/// they would otherwise style each other's components. Components sharing the
+/// same stylesheet share its scope, which is harmless.
+///
+/// The scope ID depends on nothing but the stylesheet paths as written, their
+/// contents once `theme` has picked its sections, the options rewriting the
+/// CSS (`scope = false`, `strategy`, `wrapper = false`, `dir`, `flatten`,
+/// `keep_comments`, `dedupe`, `scope_keyframes` and `scope_vars`), and, with
+/// `features(...)`, which variant the Cargo features of the crate select. A
+/// scope ID thus always stands for a single stylesheet. The
+/// server and the WebAssembly builds of an application agree on it whatever
+/// their target and profile, as long as they select the same variants. The
+/// `minify`, `prefix` and `sourcemap` features of `leptos_styles` change the
+/// CSS inside the `<style>`, never the ID; enable the same ones for both
+/// builds to render the same text as well.
+///
+/// The view is wrapped however the function produces it, from a trailing
+/// expression or from an early `return`.
+///
+/// On a `mod`, `#[styles]` styles every `#[component]` function of the
+/// module, with the options given applied to each. Without a stylesheet, each
+/// function gets the file named after it in snake case, `primary_button.css`
+/// for `PrimaryButton`, resolved like a path given to the attribute, so next
+/// to the source file containing the module unless `root = "manifest"`; a
+/// missing file is a compile error naming the function. Functions with a
+/// `#[styles]` of their own, other items and nested modules are left alone,
+/// and the module must be written in line. Because the module is expanded
+/// before the functions in it, `class_prop` and `forward` can be used.
+///
+/// The CSS is checked while it is being scoped: unbalanced braces, brackets or
+/// parentheses, unterminated strings and comments, and declarations outside of
+/// a rule are reported as compile errors on the attribute, with the line and
+/// column of the problem. Declaration blocks themselves are not validated, so
+/// nesting and custom properties are accepted as is.
+///
+/// With the `leptos` feature of `leptos_styles` enabled, a `StyleRegistry`
+/// provided through context takes the stylesheet instead, so it can be emitted
+/// in the document `<head>` during server-side rendering. Either way the
+/// `<style>` is tagged with a `data-style-id` attribute naming the scope, and
+/// a component hydrating in the browser skips its stylesheet when the server
+/// already put it in the document.
+///
+/// With the `meta` feature, a server-rendered component whose app provides a
+/// `leptos_meta` context (`provide_meta_context`) renders its stylesheet with
+/// `leptos_meta`'s `<Style>`, which puts it in the `<head>` with the app's
+/// other metadata. Without the context, the `<style>` is inlined as usual.
+/// In the browser, components keep injecting their own `<style>`, as
+/// `leptos_meta` removes a `<Style>` with the component that rendered it.
+///
+/// Under a Content Security Policy, the nonce leptos provides through context
+/// (`leptos::nonce::provide_nonce`) is set as the `nonce` of the `<style>`, so
+/// the browser accepts it. Without one in context, no `nonce` is written.
+///
+/// With the `prefix` feature enabled, declarations that some browsers still
+/// only support with a vendor prefix (flexbox, `user-select`, `appearance`,
+/// `backdrop-filter`, ...) get prefixed copies, added after scoping so they
+/// stay inside the component's scope.
+///
+/// With the `minify` feature enabled, the scoped CSS is also stripped of
+/// comments and redundant whitespace before being embedded.
+///
+/// With the `bundle` feature enabled, nothing is injected at all: the scoped
+/// CSS of every component is written to one stylesheet at compile time, for
+/// the app to serve and link with `leptos_styles::link_bundle`. The bundle is
+/// `leptos_styles/bundle.css` in the target directory of the workspace, or the
+/// path in the `LEPTOS_STYLES_BUNDLE` environment variable, relative to the
+/// workspace root. Each scope appears in it once, and the bundle is the
+/// same whatever order the components were compiled in. A scope is replaced
+/// when its stylesheet is edited, and dropped once the source file of its
+/// component or one of its stylesheets is deleted.
+///
+/// With the `sourcemap` feature enabled, debug builds append an inline source
+/// map to the scoped CSS, pointing each rule back to the line and column of
+/// the rule it came from in the original stylesheet, so browser tools show
+/// positions in your files. Release builds embed the CSS without it.
+///
+/// # Arguments
+///
+/// * `path`: A string literal representing the path to the CSS file.
+///   Like with `include_str!`, the path is resolved relative to the source
+///   file containing the attribute, so `foo.rs` can use `foo.css` from the
+///   same directory; `root = "manifest"` resolves it relative to the
+///   `CARGO_MANIFEST_DIR` instead.
+///   Several paths may be given (`#[styles("base.css", "card.css")]`); their
+///   contents are concatenated in order and share a single scope, named after
+///   the first file.
+///   Files ending in `.scss` or `.sass` are compiled to CSS first, which
+///   requires the `sass` feature.
+///   Local `@import "other.css";` statements are replaced by the imported
+///   file, resolved relative to the importing file, so shared rules are scoped
+///   like the rest; a missing file or an import cycle is a compile error.
+/// * `wrapper = false`: Instead of wrapping the component, add the scope class
+///   to the root elements of the `view!` the function body ends with, keeping
+///   the DOM exactly as written. Every `view!` the function can return gets
+///   it: the branches of a trailing `if` or `match`, through `.into_view()`,
+///   and the values of `return`. A literal `class` on a root is merged with
+///   the scope. Sibling roots each get the scope class, as do the elements
+///   of a fragment (`<>...</>`) at the root, but components and
+///   `{...}` blocks at the root cannot carry it and are rejected. Each rule
+///   then matches the roots as well as their descendants: `.title` applies
+///   to a root with `class="title"` and to any `.title` inside a root.
+/// * `strategy = "attribute"`: Scope with a `data-scope-<id>` attribute rather
+///   than a class, Svelte style. The attribute is added to every HTML element
+///   of the component's `view!` invocations and appended to the last compound
+///   of each selector (`.title` becomes `.title[data-scope-card1a2b3c4d]`),
+///   which keeps specificity predictable and never collides with class names.
+///   The default is `strategy = "class"`.
+/// * `css = "..."`: Raw CSS to use instead of a file, for components too small
+///   to deserve their own stylesheet. The scope ID is then derived from the
+///   function name. Exactly one of `path` and `css` must be given.
+/// * `element = "..."`: The tag of the element wrapping the component, `div`
+///   by default. Use e.g. `element = "li"` or `element = "tr"` for components
+///   that must fit into lists or tables.
+/// * `vars(accent, text_color = theme.text())`: CSS custom properties set from
+///   Rust, for values only known at runtime. Each entry names a custom property
+///   (`--accent`, `--text-color`; underscores become dashes) and the expression
+///   giving its value, which defaults to the variable of the same name, such as
+///   a component prop. The values are formatted with `Display` once per
+///   instance and set as the `style` attribute of the scope element: the
+///   wrapper, or the root element with `wrapper = false`. Descendants inherit
+///   them, so the stylesheet can use `var(--accent)` anywhere in the
+///   component. The scope element must not have a `style` of its own, and the
+///   attribute strategy needs a wrapper to carry them.
+/// * `forward(data-active, aria-selected)`: Add a prop per attribute, named
+///   in snake case (`data_active`), and set the attribute on the scope element
+///   from it, so a parent can drive scoped CSS such as
+///   `:host([data-active])`. The props are `MaybeProp<String>`, taking a
+///   string, an `Option` or a signal of either. An attribute whose prop is
+///   absent or `None` is left off the element, and one set to `""` is
+///   rendered empty. Like `class_prop`, this needs `#[styles]` above
+///   `#[component]`.
+/// * `classes`: Also generate a module named after the function,
+///   `mod my_component_classes`, with a `&str` constant for every class the
+///   stylesheet uses (`.btn-primary` becomes `BTN_PRIMARY`). Referring to the
+///   constants instead of string literals turns a class renamed in the CSS
+///   into a compile error. Scoping does not rename classes, so the values are
+///   the names as written.
+/// * `class_prop`: Add an optional `class: Option<String>` prop to the
+///   component, whose classes are merged with the scope class of the scope
+///   element, so `<Card class="large"/>` renders `class="card1a2b3c4d large"`
+///   and parents can style the component from outside. No class is added when
+///   the prop is absent or empty. As it changes the signature, `#[styles]`
+///   must then be placed above `#[component]`.
+/// * `scope_keyframes`: Rename the stylesheet's `@keyframes` to
+///   `<name>_<id>`, along with the `animation` and `animation-name`
+///   declarations using them, so two components can both define
+///   `@keyframes pulse`. Animations the stylesheet does not define are left
+///   alone.
+/// * `scope_vars`: Likewise rename the custom properties the stylesheet
+///   declares (`--gap: 4px`) and the `var()` reading them. Properties it only
+///   reads, and those of `vars(...)`, are left alone, so they can still be set
+///   from outside.
+/// * `flatten`: Rewrite CSS nesting as plain rules before scoping, for
+///   browsers and tools without nesting support. A `&` stands for the parent
+///   selector (`&:hover`, `&.active`, `& + &`), a nested selector without one
+///   is a descendant of the parent, and `@media` or other group rules nested
+///   in a rule are hoisted around it. As with Sass, a rule's declarations are
+///   kept ahead of the rules nested in it.
+/// * `allow_empty`: Accept a stylesheet with nothing but whitespace and
+///   comments in it, which is otherwise a compile error, and render the
+///   component as if it had no `#[styles]`.
+/// * `keep_comments`: Embed the stylesheet's comments, which are otherwise
+///   removed from the CSS put in the binary and the page.
+/// * `dedupe`: Drop rules repeated verbatim, selector and declarations alike,
+///   as stylesheets assembled from several files or imports often have. Only
+///   the last copy is kept, as it is the one that counts in the cascade;
+///   rules differing in anything but surrounding whitespace are left alone.
+///   A stylesheet that lost rules this way gets no source map.
+/// * `features(dark = "card.dark.css", "high-contrast" = "card.hc.css")`: Use
+///   another stylesheet when a Cargo feature of the crate being compiled is
+///   enabled. The first listed feature that is enabled wins, and the
+///   stylesheet given by the paths or `css` is used when none is. Every
+///   variant is checked at compile time, whichever is enabled.
+/// * `theme = "dark"`: Keep only the `dark` sections of the stylesheet, which
+///   open with `/* @theme dark */` and close with `/* @endtheme */`, and drop
+///   the sections of other themes. CSS outside of any section is shared by
+///   all themes. Each theme gets a scope ID of its own.
+/// * `inject = "mount"`: In the browser, add the stylesheet to the `<head>`
+///   when the first instance is created and remove it when the last one is
+///   cleaned up, instead of keeping it for good once the first instance is
+///   created (`inject = "eager"`, the default). The count of live
+///   instances is kept per scope, so a list of them does not add and remove
+///   the stylesheet repeatedly. Server rendering is unchanged.
+/// * `scope = false`: Leave the stylesheet unscoped, for resets and other
+///   styles meant for the whole page. The CSS is rendered as written next to
+///   the component's view, without a scope element, and still only once.
+///   Options shaping the scope, such as `element` or `vars`, are rejected.
+/// * `prefix = "ui"`: Put `ui-` in front of the generated scope ID, giving
+///   `ui-card1a2b3c4d`.
+/// * `scope = "my-card"`: Use `my-card` as the scope ID instead of generating
+///   one. It is kept stable across changes to the stylesheet. Components
+///   giving the same `scope` and CSS share the scope: they render the same
+///   class, and the stylesheet is injected once however many of them are on
+///   the page. Giving the same `scope` with different CSS is a compile error.
+///   Both values must consist of ASCII letters, digits, `-` and `_`, and not
+///   start with a digit or `-`.
+/// * `dir = "rtl"`: Mirror the stylesheet for a right-to-left layout, swapping
+///   `left` and `right` in property names (`margin-left`, `border-right`,
+///   ...), in the keywords of `float`, `clear` and `text-align`, and in the
+///   four-value forms of `margin`, `padding` and `border-radius`. Logical
+///   properties, custom properties and values such as `url()` or `var()` are
+///   left alone. Nested rules are only mirrored together with `flatten`.
+///
+/// # Example
+///
+/// Assuming you have a CSS file `my_component.css` next to the component:
+/// ```css
+/// p {
+///     color: blue;
+///     font-size: 1.2em;
+/// }
+/// ```
+///
+/// You can apply these styles to a Leptos component function like this:
+///
+/// ```ignore
+/// use leptos::*;
+/// // Assuming your proc-macro crate is named `my_macros`
+/// use my_macros::styles;
+///
+/// #[component]
+/// #[styles("my_component.css")]
+/// pub fn MyComponent() -> impl IntoView {
+///     view! {
+///         <p>"Hello from MyComponent!"</p>
+///     }
+/// }
+/// ```
+///
+/// This would conceptually expand to a view structure similar to:
+/// ```html
+/// <style>
+///     .my_component1a2b3c4d p {
+///         color: blue;
+///         font-size: 1.2em;
+///     }
+/// </style>
+/// <div class="my_component1a2b3c4d">
+///     <p>Hello from MyComponent!</p>
+/// </div>
+/// ```
+#[proc_macro_attribute]
+pub fn styles(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let expanded = match parse_macro_input!(item as syn::Item) {
+        syn::Item::Fn(func) => syn::parse::<StylesArgs>(attr).and_then(|args| expand(args, func)),
+        syn::Item::Mod(module) => {
+            syn::parse::<ModuleArgs>(attr).and_then(|args| expand_module(args, module))
+        }
+        item => Err(syn::Error::new_spanned(
+            item,
+            "`#[styles]` applies to component functions and to modules of them",
+        )),
+    };
+
+    match expanded {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Scopes a view built outside of a component function.
+///
+/// `styled!` takes the same arguments as [`macro@styles`], followed by an
+/// expression producing the view, and evaluates to that view with the
+/// stylesheet applied, exactly as if it were the body of a `#[styles]`
+/// component. This is handy in closures and helpers that build views
+/// imperatively:
+///
+/// ```ignore
+/// let rows = move || {
+///     items.get().into_iter().map(|item| {
+///         styled!("row.css", element = "li", view! { <span>{item}</span> })
+///     }).collect_view()
+/// };
+/// ```
+///
+/// Inline `css` is scoped under the name `styled`.
+#[proc_macro]
+pub fn styled(input: TokenStream) -> TokenStream {
+    let StyledInput { args, view } = parse_macro_input!(input as StyledInput);
+
+    match expand_styled(args, view) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: StylesArgs, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let mut items = proc_macro2::TokenStream::new();
+    let name = component_name(&func).to_lowercase();
+    for (cfg, args, stylesheet) in variants(&args, &name)? {
+        let mut func = func.clone();
+        if args.classes.is_some() {
+            let classes = classes_module(&func, &stylesheet, args.flatten.is_some())?;
+            items.extend(quote!(#cfg #classes));
+        }
+        if let Some(flag) = &args.class_prop {
+            let class = syn::Ident::new("class", flag.span());
+            add_prop(
+                &mut func,
+                flag,
+                &class,
+                syn::parse_quote!(#[prop(optional, into)] class: Option<String>),
+            )?;
+        }
+        for forward in &args.forward {
+            let prop = &forward.prop;
+            let key = syn::Ident::new("forward", prop.span());
+            add_prop(
+                &mut func,
+                &key,
+                prop,
+                syn::parse_quote!(#[prop(optional, into)] #prop: leptos::MaybeProp<String>),
+            )?;
+        }
+        let (block, scope) = scope_body(&args, &stylesheet, *func.block)?;
+        *func.block = block;
+        if let Some(scope) = scope {
+            let vis = &func.vis;
+            let constant = quote::format_ident!(
+                "{}_SCOPE",
+                snake_case(&component_name(&func)).to_uppercase()
+            );
+            let doc = format!(
+                "The scope ID of [`{}`], `{}`, on its scope element.",
+                component_name(&func),
+                scope
+            );
+            items.extend(quote! {
+                #cfg
+                #[doc = #doc]
+                #[allow(dead_code)]
+                #vis const #constant: &str = #scope;
+            });
+        }
+        items.extend(quote!(#cfg #func));
+    }
+    Ok(items)
+}
+
+/// Applies `#[styles]` to every `#[component]` function of `module` that
+/// does not have a `#[styles]` of its own, with the file named after the
+/// function when no stylesheet is given.
+fn expand_module(
+    ModuleArgs {
+        args,
+        by_convention,
+    }: ModuleArgs,
+    mut module: syn::ItemMod,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let Some((_, items)) = &mut module.content else {
+        return Err(syn::Error::new_spanned(
+            &module,
+            "`#[styles]` needs the items of the module in line, as `mod name { ... }`",
+        ));
+    };
+    let has_attribute = |func: &ItemFn, name: &str| {
+        func.attrs.iter().any(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|last| last.ident == name)
+        })
+    };
+    let mut errors: Option<syn::Error> = None;
+    let mut report = |error: syn::Error| match &mut errors {
+        Some(errors) => errors.combine(error),
+        None => errors = Some(error),
+    };
+    let mut components = 0;
+    for item in items.iter_mut() {
+        let syn::Item::Fn(func) = item else {
+            continue;
+        };
+        if !has_attribute(func, "component") || has_attribute(func, "styles") {
+            continue;
+        }
+        components += 1;
+        let mut args = args.clone();
+        if by_convention {
+            let name = component_name(func);
+            let path =
+                syn::LitStr::new(&format!("{}.css", snake_case(&name)), func.sig.ident.span());
+            let file = load::resolve(&path, args.root);
+            if !file.is_file() {
+                let error = syn::Error::new(
+                    func.sig.ident.span(),
+                    format!(
+                        "no stylesheet for `{}`: expected `{}` relative to {}, at `{}`",
+                        name,
+                        path.value(),
+                        args.root.describe(),
+                        file.display()
+                    ),
+                );
+                report(error);
+                continue;
+            }
+            args.source = args::Source::Files(vec![path]);
+        }
+        match expand(args, func.clone()) {
+            Ok(tokens) => *item = syn::Item::Verbatim(tokens),
+            Err(error) => report(error),
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    if components == 0 {
+        return Err(syn::Error::new(
+            module.ident.span(),
+            "no `#[component]` function in this module for `#[styles]` to style",
+        ));
+    }
+    Ok(quote!(#module))
+}
+
+/// Splits `args` into one set of arguments per stylesheet variant, each with
+/// the `#[cfg]` attribute selecting it and its loaded stylesheet.
+///
+/// The first variant whose feature is enabled wins, and the main stylesheet
+/// is used when none is. Every variant is loaded, whichever is enabled, and
+/// they all share the name of the main stylesheet so the scope reads the same.
+fn variants(
+    args: &StylesArgs,
+    inline_name: &str,
+) -> syn::Result<Vec<(proc_macro2::TokenStream, StylesArgs, load::LoadedStylesheet)>> {
+    let main = load_stylesheet(args, inline_name)?;
+    if args.features.is_empty() {
+        return Ok(vec![(quote!(), args.clone(), main)]);
+    }
+    let features: Vec<&syn::LitStr> = args
+        .features
+        .iter()
+        .map(|variant| &variant.feature)
+        .collect();
+    let mut variants = Vec::with_capacity(features.len() + 1);
+    for (i, variant) in args.features.iter().enumerate() {
+        let earlier = &features[..i];
+        let feature = &variant.feature;
+        let cfg = quote!(#[cfg(all(feature = #feature, not(any(#(feature = #earlier),*))))]);
+        let mut args = args.clone();
+        args.source = args::Source::Files(vec![variant.path.clone()]);
+        let mut stylesheet = load_stylesheet(&args, inline_name)?;
+        stylesheet.name.clone_from(&main.name);
+        variants.push((cfg, args, stylesheet));
+    }
+    variants.push((
+        quote!(#[cfg(not(any(#(feature = #features),*)))]),
+        args.clone(),
+        main,
+    ));
+    Ok(variants)
+}
+
+/// Loads the stylesheet of `args`, keeping only the sections of its `theme`.
+fn load_stylesheet(args: &StylesArgs, inline_name: &str) -> syn::Result<load::LoadedStylesheet> {
+    let mut stylesheet = load::load_stylesheet(&args.source, args.root, inline_name)?;
+    let Some(theme) = &args.theme else {
+        return Ok(stylesheet);
+    };
+    let mut names: Vec<String> = Vec::new();
+    for part in &mut stylesheet.parts {
+        let sections = css::theme_sections(&part.source)
+            .map_err(|e| syn::Error::new(part.span, e.describe(&part.source, &part.origin())))?;
+        for section in &sections {
+            if !names.contains(&section.name) {
+                names.push(section.name.clone());
+            }
+        }
+        part.source = css::select_theme(&part.source, &sections, &theme.value());
+    }
+    if !names.contains(&theme.value()) {
+        let message = match names.as_slice() {
+            [] => "the stylesheet has no `/* @theme <name> */` sections to choose from".to_string(),
+            names => format!(
+                "unknown theme `{}`; the stylesheet defines {}",
+                theme.value(),
+                names
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        return Err(syn::Error::new(theme.span(), message));
+    }
+    Ok(stylesheet)
+}
+
+fn expand_styled(args: StylesArgs, view: syn::Expr) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(flag) = args.classes.as_ref().or(args.class_prop.as_ref()) {
+        return Err(syn::Error::new(
+            flag.span(),
+            format!("`{}` is only supported by the `#[styles]` attribute", flag),
+        ));
+    }
+    if let Some(forward) = args.forward.first() {
+        return Err(syn::Error::new(
+            forward.prop.span(),
+            "`forward` is only supported by the `#[styles]` attribute",
+        ));
+    }
+    let mut views = proc_macro2::TokenStream::new();
+    for (cfg, args, stylesheet) in variants(&args, "styled")? {
+        let (block, _) = scope_body(&args, &stylesheet, syn::parse_quote!({ #view }))?;
+        views.extend(quote!(#cfg let styled_view = #block;));
+    }
+    Ok(quote!({
+        #views
+        styled_view
+    }))
+}
+
+/// Generates `mod <function>_classes`, with a constant for every class name
+/// the stylesheet uses: `.btn-primary` becomes `BTN_PRIMARY = "btn-primary"`.
+/// With `flatten`, classes of nested rules are included.
+///
+/// Scoping adds the scope around the rules without renaming any class, so the
+/// values are the names as written in the CSS.
+fn classes_module(
+    func: &ItemFn,
+    stylesheet: &load::LoadedStylesheet,
+    flatten: bool,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut constants: Vec<(String, syn::Ident)> = Vec::new();
+    for part in &stylesheet.parts {
+        let source = if flatten {
+            css::flatten_nesting(&part.source)
+        } else {
+            Ok(part.source.clone())
+        };
+        let names = source
+            .and_then(|source| css::class_names(&source))
+            .map_err(|e| syn::Error::new(part.span, e.describe(&part.source, &part.origin())))?;
+        for name in names {
+            let constant = name.to_uppercase().replace('-', "_");
+            let ident = syn::parse_str::<syn::Ident>(&constant).map_err(|_| {
+                syn::Error::new(
+                    part.span,
+                    format!("class `{}` cannot be turned into a constant name", name),
+                )
+            })?;
+            match constants.iter().find(|(_, existing)| *existing == ident) {
+                Some((existing, _)) if *existing != name => {
+                    return Err(syn::Error::new(
+                        part.span,
+                        format!(
+                            "classes `{}` and `{}` would both be named `{}`",
+                            existing, name, constant
+                        ),
+                    ));
+                }
+                Some(_) => {}
+                None => constants.push((name, ident)),
+            }
+        }
+    }
+
+    let vis = &func.vis;
+    let module = quote::format_ident!("{}_classes", snake_case(&component_name(func)));
+    let module_doc = format!(
+        "Class names of the stylesheet of [`{}`].",
+        component_name(func)
+    );
+    let items = constants.iter().map(|(name, ident)| {
+        let doc = format!("`.{}`", name);
+        quote! {
+            #[doc = #doc]
+            pub const #ident: &str = #name;
+        }
+    });
+    Ok(quote! {
+        #[doc = #module_doc]
+        #[allow(dead_code)]
+        #vis mod #module {
+            #(#items)*
+        }
+    })
+}
+
+/// Adds the prop `arg`, named `name`, to a component for the `option` asking
+/// for it. The component must not have been expanded by `#[component]` yet
+/// for the prop to be picked up.
+fn add_prop(
+    func: &mut ItemFn,
+    option: &syn::Ident,
+    name: &syn::Ident,
+    arg: syn::FnArg,
+) -> syn::Result<()> {
+    let is_component = func.attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "component")
+    });
+    if !is_component {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "`{}` adds a prop to the component, so `#[styles]` must be placed above `#[component]`",
+                option
+            ),
+        ));
+    }
+    let exists = func.sig.inputs.iter().any(|input| {
+        matches!(input, syn::FnArg::Typed(arg)
+            if matches!(&*arg.pat, syn::Pat::Ident(pat) if pat.ident == *name))
+    });
+    if exists {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("the component already has a `{}` prop", name),
+        ));
+    }
+    func.sig.inputs.push(arg);
+    Ok(())
+}
+
+/// The name of the component `func` implements. Below `#[component]`, the
+/// function is leptos' `__Card` rather than `Card`.
+fn component_name(func: &ItemFn) -> String {
+    let name = func.sig.ident.to_string();
+    match name.strip_prefix("__") {
+        Some(component) if !component.is_empty() => component.to_string(),
+        _ => name,
+    }
+}
+
+/// Converts a function name such as `MyComponent` to `my_component`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev != '_' && (prev.is_lowercase() || prev.is_ascii_digit() || next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+/// Rewrites `original_body`, a block evaluating to a view, so that it renders
+/// the view scoped by `stylesheet` as `args` describe. Also returns the ID of
+/// the scope the view is put in, if it is put in one.
+fn scope_body(
+    args: &StylesArgs,
+    stylesheet: &load::LoadedStylesheet,
+    mut original_body: Block,
+) -> syn::Result<(Block, Option<String>)> {
+    // Nothing to inject, so neither the `<style>` nor a scope element.
+    if is_unstyled(args, stylesheet)? {
+        return Ok((unstyled_body(args, original_body)?, None));
+    }
+    let (unique_id, claim) = scope_id(args, stylesheet);
+    claim_scope(args, stylesheet, &unique_id, claim)?;
+
+    let scope = match args.strategy {
+        // The roots carry the class themselves, so rules must match them too.
+        Strategy::Class if !args.wrapper => css::Scope::Root(unique_id.clone()),
+        Strategy::Class => css::Scope::Class(unique_id.clone()),
+        Strategy::Attribute => css::Scope::Attribute(format!("data-scope-{}", unique_id)),
+    };
+    // What ties an element to the scope, in `view!` syntax.
+    let marker: proc_macro2::TokenStream = match &scope {
+        css::Scope::Class(class) | css::Scope::Root(class) => quote!(class=#class),
+        css::Scope::Attribute(name) => syn::parse_str(&format!("{}=\"\"", name))?,
+    };
+    let scoped_css = scoped_css(args, stylesheet, scope, &unique_id)?;
+    let warnings = page_warnings(args, stylesheet)?;
+    #[cfg(feature = "bundle")]
+    let bundled = {
+        write_bundle(args, stylesheet, &unique_id, &scoped_css)?;
+        true
+    };
+    #[cfg(not(feature = "bundle"))]
+    let bundled = false;
+    // Debug builds of the user's crate get the stylesheet with its source map.
+    #[cfg(feature = "sourcemap")]
+    let scoped_css =
+        match sourcemap::source_map(&scoped_css, &stylesheet.parts, args.flatten.is_some()) {
+            Some(map) => {
+                let mapped = format!("{}\n{}", scoped_css, map);
+                quote!(if cfg!(debug_assertions) { #mapped } else { #scoped_css })
+            }
+            None => quote!(#scoped_css),
+        };
+
+    // Makes cargo rebuild the component whenever a stylesheet changes.
+    let tracked = stylesheet.parts.iter().flat_map(|part| &part.tracked_paths);
+
+    let (style, style_attr) = vars_tokens(args)?;
+    let (forward, forward_attrs) = forward_tokens(args)?;
+    // The classes of the `class` prop, merged into those of the scope element.
+    let scope_classes = quote::format_ident!("scope_classes");
+    let (classes, classes_attr) = class_prop_tokens(args, &unique_id, &scope_classes)?;
+
+    if args.strategy == Strategy::Attribute {
+        let marked = view::mark_views(quote!(#original_body), &marker);
+        original_body = syn::parse2(marked)?;
+    }
+    let view = if args.global {
+        // Rendered beside the stylesheet, as written.
+        quote! { {original_view} }
+    } else if args.wrapper {
+        let element: proc_macro2::TokenStream = syn::parse_str(&args.element)?;
+        let marker = match (&args.class_prop, args.strategy) {
+            (Some(_), Strategy::Class) => view::spread_attribute("class", quote!(#scope_classes)),
+            _ => marker,
+        };
+        quote! {
+            <#element #marker #classes_attr #style_attr #forward_attrs>
+                {original_view}
+            </#element>
+        }
+    } else if args.strategy == Strategy::Attribute {
+        // Every element already carries the scope attribute.
+        quote! { {original_view} }
+    } else {
+        let views = view::result_views(&mut original_body);
+        if views.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &original_body,
+                "`wrapper = false` requires the function body to produce its view with `view!`, whose root elements get the scope",
+            ));
+        }
+        let root_class = match args.class_prop {
+            Some(_) => view::RootClass::Variable(&scope_classes),
+            None => view::RootClass::Scope(&unique_id),
+        };
+        for mac in views {
+            view::scope_root(mac, &root_class, style_attr.clone(), forward_attrs.clone())
+                .map_err(|e| syn::Error::new_spanned(&*mac, format!("`wrapper = false`: {}", e)))?;
+        }
+        quote! { {original_view} }
+    };
+
+    let (inject, style_element) = inject_tokens(args, &unique_id, &scoped_css, bundled);
+    // A `return` would leave the function with the unstyled view, so such a
+    // body becomes a closure, whose value is the view however it is produced.
+    let capture = if has_return(quote!(#original_body)) {
+        quote! {
+            #[allow(clippy::redundant_closure_call)]
+            let original_view = (|| #original_body)();
+        }
+    } else {
+        quote!(let original_view = #original_body;)
+    };
+    let new_body = quote! {
+        {
+            #(const _: &str = include_str!(#tracked);)*
+            #warnings
+            #inject
+            #style
+            #classes
+            #forward
+            #capture
+            leptos::view! {
+                #style_element
+                #view
+            }
+        }
+    };
+
+    let scope = (!args.global).then_some(unique_id);
+    Ok((syn::parse2(new_body)?, scope))
+}
+
+/// Returns whether `stylesheet` has no CSS at all, which `allow_empty` lets
+/// the component be rendered unstyled with. Without it, any file of the
+/// stylesheet without CSS is an error.
+fn is_unstyled(args: &StylesArgs, stylesheet: &load::LoadedStylesheet) -> syn::Result<bool> {
+    // A file with nothing but whitespace and comments is almost always one
+    // that has not been written yet.
+    let is_empty =
+        |part: &&load::StylesheetPart| css::strip_comments(&part.source).trim().is_empty();
+    match args.allow_empty {
+        None => {
+            if let Some(part) = stylesheet.parts.iter().find(is_empty) {
+                return Err(syn::Error::new(
+                    part.span,
+                    format!(
+                        "{} contains no CSS; write some, or add `allow_empty` to render the component unstyled",
+                        part.origin()
+                    ),
+                ));
+            }
+            Ok(false)
+        }
+        Some(_) => Ok(stylesheet.parts.iter().all(|part| is_empty(&part))),
+    }
+}
+
+/// Returns `original_body` as it is, with the props and values the options
+/// would have used marked as used.
+fn unstyled_body(args: &StylesArgs, mut original_body: Block) -> syn::Result<Block> {
+    let class = args.class_prop.as_ref().map(|_| quote!(let _ = class;));
+    let values = args.vars.iter().map(|var| &var.value);
+    let props = args.forward.iter().map(|forward| &forward.prop);
+    let unused: Block = syn::parse2(quote!({ #class #(let _ = &#values;)* #(let _ = &#props;)* }))?;
+    original_body.stmts.splice(0..0, unused.stmts);
+    Ok(original_body)
+}
+
+/// Claims `unique_id` for `stylesheet`, failing when another component of the
+/// crate already holds it with a different stylesheet, as identified by
+/// `claim`.
+fn claim_scope(
+    args: &StylesArgs,
+    stylesheet: &load::LoadedStylesheet,
+    unique_id: &str,
+    claim: Vec<u8>,
+) -> syn::Result<()> {
+    // Two different stylesheets hashing to the same scope would silently
+    // style each other's components. Outside of a macro expansion, as in unit
+    // tests, there are no other components.
+    if !proc_macro::is_available() {
+        return Ok(());
+    }
+    let span = stylesheet.parts[0].span;
+    let origin = stylesheet
+        .parts
+        .iter()
+        .map(|part| part.origin())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let location = format!("{}:{}", span.unwrap().file(), span.unwrap().line());
+    let claimed_by = format!("{} ({})", origin, location);
+    // The components of a `#[styles]` module share the call site, but not
+    // the span of their stylesheet.
+    let call_site = proc_macro::Span::call_site();
+    let site = format!(
+        "{}:{}:{} {}:{}",
+        call_site.file(),
+        call_site.line(),
+        call_site.column(),
+        span.unwrap().line(),
+        span.unwrap().column()
+    );
+    if let Err(other) = scopes::claim(unique_id, claim, site, claimed_by) {
+        let message = match args.scope {
+            Some(_) => format!(
+                "the scope `{}` is already used by {} with a different stylesheet; components sharing a scope must share its CSS",
+                unique_id, other
+            ),
+            None => format!(
+                "the scope `{}` of {} collides with the scope of {}; rename one of them",
+                unique_id, origin, other
+            ),
+        };
+        return Err(syn::Error::new(span, message));
+    }
+    Ok(())
+}
+
+/// Runs the passes `args` ask for over the files of `stylesheet`, scoped to
+/// `scope` unless it is global, and returns the CSS to embed.
+fn scoped_css(
+    args: &StylesArgs,
+    stylesheet: &load::LoadedStylesheet,
+    scope: css::Scope,
+    unique_id: &str,
+) -> syn::Result<String> {
+    let mut pipeline = transform::Pipeline::new((!args.global).then_some(scope));
+    pipeline.flatten = args.flatten.is_some();
+    pipeline.keep_comments = args.keep_comments.is_some();
+    pipeline.dedupe = args.dedupe.is_some();
+    pipeline.dir = args.dir;
+    // Keyframes and custom properties defined in one file can be used in
+    // another, so they are gathered across all of them before renaming.
+    let keyframes = args.scope_keyframes.is_some();
+    let properties = args.scope_vars.is_some();
+    if keyframes || properties {
+        let mut locals = css::Locals::default();
+        for part in &stylesheet.parts {
+            locals
+                .collect(&part.source, keyframes, properties)
+                .map_err(|e| {
+                    syn::Error::new(part.span, e.describe(&part.source, &part.origin()))
+                })?;
+        }
+        // Properties set through `vars(...)` come from outside the stylesheet.
+        locals
+            .properties
+            .retain(|property| !args.vars.iter().any(|var| var.property() == *property));
+        pipeline.locals = Some((locals, format!("_{}", unique_id)));
+    }
+
+    let describe = |part: &load::StylesheetPart, e: css::ParseError| {
+        syn::Error::new(part.span, e.describe(&part.source, &part.origin()))
+    };
+    // A single file without options is scoped by `transform::scope_css`,
+    // the function the string-in, string-out tests of the passes check.
+    let default_passes = !(pipeline.flatten || pipeline.keep_comments || pipeline.dedupe)
+        && pipeline.dir == Direction::Ltr
+        && pipeline.locals.is_none();
+    match (&pipeline.scope, stylesheet.parts.as_slice()) {
+        (Some(css::Scope::Class(id)), [part]) if default_passes => {
+            transform::scope_css(&part.source, id).map_err(|e| describe(part, e))
+        }
+        _ => {
+            let sources: Vec<&str> = stylesheet
+                .parts
+                .iter()
+                .map(|part| part.source.as_str())
+                .collect();
+            pipeline
+                .run(&sources)
+                .map_err(|(index, e)| describe(&stylesheet.parts[index], e))
+        }
+    }
+}
+
+/// Warns about the selectors of `stylesheet` that scoping under a class puts
+/// out of reach.
+fn page_warnings(
+    args: &StylesArgs,
+    stylesheet: &load::LoadedStylesheet,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // Behind a class, `body .title` becomes `.card1234 body .title`, which
+    // cannot match. The rest of the stylesheet still works, so this only
+    // warns.
+    let mut warnings = proc_macro2::TokenStream::new();
+    if args.strategy != Strategy::Class || args.global {
+        return Ok(warnings);
+    }
+    for part in &stylesheet.parts {
+        let selectors = css::page_selectors(&part.source, args.flatten.is_some())
+            .map_err(|e| syn::Error::new(part.span, e.describe(&part.source, &part.origin())))?;
+        for (offset, selector) in selectors {
+            let line = part.source[..offset].matches('\n').count() + 1;
+            warnings.extend(warning(
+                "selector_never_matches",
+                part.span,
+                &format!(
+                    "`{}` in {} at line {} can never match, as scoping puts it inside the component, where there is no `html`, `body` or `:root`; wrap the page-level part in `:global(...)`",
+                    selector,
+                    part.origin(),
+                    line
+                ),
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+/// Writes the scoped CSS of `stylesheet` to the bundle.
+#[cfg(feature = "bundle")]
+fn write_bundle(
+    args: &StylesArgs,
+    stylesheet: &load::LoadedStylesheet,
+    unique_id: &str,
+    scoped_css: &str,
+) -> syn::Result<()> {
+    // Outside of a macro expansion, as in unit tests, there is no crate to
+    // bundle for.
+    if !proc_macro::is_available() {
+        return Ok(());
+    }
+    // The scope is known by the crate and source file of the component,
+    // and by everything its ID is derived from but the CSS itself, none
+    // of which changes when the stylesheet is edited.
+    let call_site = proc_macro::Span::call_site();
+    let source_file = call_site.local_file();
+    let key_input = [
+        std::env::var("CARGO_PKG_NAME").unwrap_or_default(),
+        call_site.file(),
+        stylesheet.name.clone(),
+        args.prefix.clone().unwrap_or_default(),
+        args.scope.clone().unwrap_or_default(),
+        args.theme
+            .as_ref()
+            .map(|theme| theme.value())
+            .unwrap_or_default(),
+    ]
+    .into_iter()
+    .chain(stylesheet.parts.iter().map(|part| part.path.clone()))
+    .flat_map(|text| text.into_bytes().into_iter().chain([0]))
+    .chain(css_options(args));
+    let key = format!("{:08x}", djb2(key_input));
+    // Relative paths are relative to the directory rustc runs in, which
+    // other crates may not share.
+    let sources: Vec<String> = source_file
+        .and_then(|path| Some(std::env::current_dir().ok()?.join(path)))
+        .map(|path| path.to_string_lossy().into_owned())
+        .into_iter()
+        .chain(
+            stylesheet
+                .parts
+                .iter()
+                .flat_map(|part| part.tracked_paths.clone()),
+        )
+        .collect();
+    bundle::write(unique_id, &key, scoped_css, &sources).map_err(|e| {
+        syn::Error::new(
+            stylesheet.parts[0].span,
+            format!("cannot add the stylesheet to the CSS bundle: {}", e),
+        )
+    })
+}
+
+/// Returns the statement evaluating the custom properties of `vars(...)` once
+/// per instance, and the attribute setting them on the scope element.
+fn vars_tokens(
+    args: &StylesArgs,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    Ok(if args.vars.is_empty() {
+        (quote!(), quote!())
+    } else {
+        if !args.wrapper && args.strategy == Strategy::Attribute {
+            return Err(syn::Error::new(
+                args.vars[0].name.span(),
+                "`vars` needs a scope element: drop `wrapper = false` or use the class strategy",
+            ));
+        }
+        let declarations = args
+            .vars
+            .iter()
+            .map(|var| format!("{}: {{}};", var.property()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let values = args.vars.iter().map(|var| &var.value);
+        (
+            quote!(let scope_style = format!(#declarations, #(#values),*);),
+            view::spread_attribute("style", quote!(scope_style)),
+        )
+    })
+}
+
+/// Returns the statements copying the props of `forward(...)`, and the
+/// attributes setting them on the scope element for as long as they hold a
+/// value.
+fn forward_tokens(
+    args: &StylesArgs,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    // The body may move the props, so this keeps copies.
+    Ok(if args.forward.is_empty() {
+        (quote!(), quote!())
+    } else {
+        if !args.wrapper && args.strategy == Strategy::Attribute {
+            return Err(syn::Error::new(
+                args.forward[0].prop.span(),
+                "`forward` needs a scope element: drop `wrapper = false` or use the class strategy",
+            ));
+        }
+        let props: Vec<&syn::Ident> = args.forward.iter().map(|forward| &forward.prop).collect();
+        let copies: Vec<syn::Ident> = props
+            .iter()
+            .map(|prop| quote::format_ident!("forwarded_{}", prop))
+            .collect();
+        let attributes = args
+            .forward
+            .iter()
+            .map(|forward| syn::parse_str::<proc_macro2::TokenStream>(&forward.attribute))
+            .collect::<syn::Result<Vec<_>>>()?;
+        (
+            quote!(#(let #copies = ::std::clone::Clone::clone(&#props);)*),
+            quote!(#(#attributes={move || leptos::SignalGet::get(&#copies)})*),
+        )
+    })
+}
+
+/// Returns the statement merging the classes of the `class` prop into those
+/// of the scope element as `scope_classes`, and the attribute setting them
+/// when the scope class does not carry them.
+fn class_prop_tokens(
+    args: &StylesArgs,
+    unique_id: &str,
+    scope_classes: &syn::Ident,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    Ok(match (&args.class_prop, args.strategy) {
+        (None, _) => (quote!(), quote!()),
+        (Some(_), Strategy::Class) => (
+            quote! {
+                let #scope_classes = match class.as_deref().map(str::trim) {
+                    Some(class) if !class.is_empty() => format!("{} {}", #unique_id, class),
+                    _ => #unique_id.to_string(),
+                };
+            },
+            quote!(),
+        ),
+        (Some(flag), Strategy::Attribute) => {
+            if !args.wrapper {
+                return Err(syn::Error::new(
+                    flag.span(),
+                    "`class_prop` needs a scope element: drop `wrapper = false` or use the class strategy",
+                ));
+            }
+            (
+                quote! {
+                    let #scope_classes = class.filter(|class| !class.trim().is_empty());
+                },
+                view::spread_attribute("class", quote!(#scope_classes)),
+            )
+        }
+    })
+}
+
+/// Returns the statements adding the stylesheet to the document, and the
+/// `<style>` element rendered beside the view when they do not.
+fn inject_tokens(
+    args: &StylesArgs,
+    unique_id: &str,
+    scoped_css: &impl quote::ToTokens,
+    bundled: bool,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    #[cfg(feature = "registry")]
+    let register = quote! {
+        // A provided `StyleRegistry` collects the stylesheet for the document
+        // head, in which case nothing is inlined.
+        let registered = leptos::use_context::<::leptos_styles::StyleRegistry>()
+            .map(|registry| registry.register(#unique_id, #scoped_css))
+            .is_some();
+    };
+    #[cfg(not(feature = "registry"))]
+    let register = quote! {
+        let registered = false;
+    };
+    // On the server, a provided `leptos_meta` context renders the stylesheet
+    // in the head. In the browser, `leptos_meta` would remove it along with
+    // the first instance, so the usual injection takes over.
+    #[cfg(feature = "meta")]
+    let register = quote! {
+        #register
+        #[cfg(not(target_arch = "wasm32"))]
+        let registered = registered || ::leptos_styles::meta_style(#unique_id, #scoped_css);
+    };
+
+    let style_selector = format!("style[data-style-id=\"{}\"]", unique_id);
+    let head_selector = format!("head > {}", style_selector);
+    let mount = match args.inject {
+        Inject::Eager => quote!(let _ = style;),
+        // The number of mounted instances is kept on the `<style>` itself, so
+        // that components sharing a scope share the count.
+        Inject::Mount => quote! {
+            let mounts = style.get_attribute("data-style-mounts");
+            let _ = style.set_attribute(
+                "data-style-mounts",
+                &::leptos_styles::mounted(mounts.as_deref()),
+            );
+            leptos::on_cleanup(move || {
+                let mounts = style.get_attribute("data-style-mounts");
+                match ::leptos_styles::unmounted(mounts.as_deref()) {
+                    Some(mounts) => {
+                        let _ = style.set_attribute("data-style-mounts", &mounts);
+                    }
+                    None => style.remove(),
+                }
+            });
+        },
+    };
+    // Bundled stylesheets are served by the app, so nothing is injected.
+    // The variable is read through `option_env!` for cargo to notice when it
+    // changes.
+    if bundled {
+        (
+            quote!(
+                const _: Option<&str> = option_env!("LEPTOS_STYLES_BUNDLE");
+            ),
+            quote!(),
+        )
+    } else {
+        let inject = quote! {
+            #register
+            // On the server, the first instance a runtime renders (a single
+            // request) carries the stylesheet, for every component of its
+            // scope.
+            #[cfg(not(target_arch = "wasm32"))]
+            let inject_style = !registered && {
+                let (first, rendered) =
+                    ::leptos_styles::inline_style(leptos::current_runtime(), #unique_id);
+                leptos::on_cleanup(move || drop(rendered));
+                first
+            };
+            // In the browser, the stylesheet goes in the head, where it
+            // outlives the instance adding it. One the server rendered,
+            // inline or in the head, is moved there rather than added again.
+            #[cfg(target_arch = "wasm32")]
+            let inject_style = {
+                let _ = registered;
+                let document = leptos::document();
+                let head = document.query_selector("head").ok().flatten();
+                let style = ::leptos_styles::head_style(
+                    document.query_selector(#head_selector).ok().flatten(),
+                    || document.query_selector(#style_selector).ok().flatten(),
+                    || {
+                        let style = document.create_element("style").ok()?;
+                        let _ = style.set_attribute("data-style-id", #unique_id);
+                        if let Some(nonce) = leptos::nonce::use_nonce() {
+                            let _ = style.set_attribute("nonce", &nonce.to_string());
+                        }
+                        style.set_text_content(Some(#scoped_css));
+                        Some(style)
+                    },
+                    |style| head.as_ref().is_some_and(|head| head.append_child(style).is_ok()),
+                );
+                if let Some(style) = style {
+                    #mount
+                }
+                false
+            };
+        };
+        let style_element = quote! {
+            {inject_style.then(|| {
+                // Through a variable, as a literal child would end up in the
+                // template `view!` formats on the server, braces and all.
+                let css: &'static str = #scoped_css;
+                leptos::view! {
+                    <style data-style-id=#unique_id nonce={leptos::nonce::use_nonce()}>
+                        {css}
+                    </style>
+                }
+            })}
+        };
+        (inject, style_element)
+    }
+}
+
+/// Returns the scope ID of `stylesheet`, along with the bytes identifying the
+/// stylesheet it stands for, which another component may only use the ID with
+/// if they are the same.
+fn scope_id(args: &StylesArgs, stylesheet: &load::LoadedStylesheet) -> (String, Vec<u8>) {
+    // The paths keep identically named files in different directories apart,
+    // and the contents give every revision of a stylesheet a fresh scope.
+    // Nothing depending on the target or on the features of this crate goes
+    // in, so the server and the client derive the same ID.
+    let mut hash_input: Vec<u8> = stylesheet
+        .parts
+        .iter()
+        .flat_map(|part| {
+            (part.path.bytes().chain([0]))
+                .chain(part.source.bytes())
+                .chain([0])
+        })
+        .collect();
+    let options = css_options(args);
+    hash_input.extend_from_slice(&options);
+    match (&args.scope, &args.prefix) {
+        // A scope ID set by hand can be shared by components with the same
+        // CSS, wherever their files are.
+        (Some(scope), _) => {
+            let mut claim: Vec<u8> = stylesheet
+                .parts
+                .iter()
+                .flat_map(|part| part.source.bytes().chain([0]))
+                .collect();
+            claim.extend(options);
+            (scope.clone(), claim)
+        }
+        (None, prefix) => (
+            format!(
+                "{}{}{:08x}",
+                prefix
+                    .as_ref()
+                    .map_or(String::new(), |prefix| format!("{}-", prefix)),
+                stylesheet.name,
+                djb2(hash_input.iter().copied())
+            ),
+            hash_input,
+        ),
+    }
+}
+
+/// Returns the options of `args` rewriting the CSS, as bytes to hash. Each
+/// makes a different stylesheet of the same files, so that an ID always
+/// stands for one text, which the page only needs once. The defaults add
+/// nothing.
+fn css_options(args: &StylesArgs) -> Vec<u8> {
+    let class = args.strategy == Strategy::Class;
+    let mut options = Vec::new();
+    for (set, option) in [
+        (args.global, "global"),
+        (!class, "attribute"),
+        (class && !args.wrapper && !args.global, "root"),
+        (args.dir == Direction::Rtl, "rtl"),
+        (args.flatten.is_some(), "flatten"),
+        (args.keep_comments.is_some(), "comments"),
+        (args.dedupe.is_some(), "dedupe"),
+        (args.scope_keyframes.is_some(), "keyframes"),
+    ] {
+        if set {
+            options.extend(option.bytes().chain([0]));
+        }
+    }
+    if args.scope_vars.is_some() {
+        options.extend(b"vars\0");
+        for var in &args.vars {
+            options.extend(var.property().bytes().chain([0]));
+        }
+    }
+    options
+}
+
+/// Returns whether `tokens` contain a `return`, possibly one of a closure.
+fn has_return(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == "return",
+        proc_macro2::TokenTree::Group(group) => has_return(group.stream()),
+        _ => false,
+    })
+}
+
+/// Emits `message` as a compiler warning pointing at `span`.
+///
+/// Procedural macros have no stable way to warn, so this calls a deprecated
+/// function named `name` at `span`, which rustc reports as "use of deprecated
+/// function `name`: message". `name` should say what is wrong on its own.
+fn warning(name: &str, span: proc_macro2::Span, message: &str) -> proc_macro2::TokenStream {
+    let item = syn::Ident::new(name, span);
+    quote::quote_spanned! {span=>
+        {
+            #[deprecated(note = #message)]
+            fn #item() {}
+            #item();
+        }
+    }
+}
+
+/// Hashes `bytes` with the 64-bit variant of djb2, folded down to 32 bits.
+///
+/// Scope IDs end up in rendered pages, CSS bundles and the `classes` module,
+/// which are not always rebuilt together, so this must never change, not even
+/// for a better hash.
+fn djb2(bytes: impl IntoIterator<Item = u8>) -> u32 {
+    let mut hash: u64 = 5381;
+    for byte in bytes {
+        hash = hash.wrapping_mul(33).wrapping_add(byte as u64);
+    }
+    (hash ^ (hash >> 32)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stylesheet_scope_id(args: &str, name: &str) -> String {
+        let args: StylesArgs = syn::parse_str(args).unwrap();
+        let stylesheet = load_stylesheet(&args, name).unwrap();
+        scope_id(&args, &stylesheet).0
+    }
+
+    // Scope IDs end up in pages and bundles built at other times, so these
+    // values must never change.
+    #[test]
+    fn hash_is_pinned() {
+        assert_eq!(djb2(*b""), 0x0000_1505);
+        assert_eq!(djb2(*b"a"), 0x0002_b606);
+        assert_eq!(djb2(*b"card.css\0.card { color: red; }\0"), 0x87b5_0ff1);
+    }
+
+    #[test]
+    fn scope_ids_are_pinned() {
+        let css = r#"css = ".card { color: red; }""#;
+        assert_eq!(stylesheet_scope_id(css, "card"), "carde8ee3103");
+        assert_eq!(
+            stylesheet_scope_id(&format!("{}, prefix = \"ui\"", css), "card"),
+            "ui-carde8ee3103"
+        );
+        assert_eq!(
+            stylesheet_scope_id(&format!("{}, scope = false", css), "card"),
+            "card4bc7d3fe"
+        );
+        assert_eq!(
+            stylesheet_scope_id(&format!("{}, scope = \"my-card\"", css), "card"),
+            "my-card"
+        );
+    }
+
+    #[test]
+    fn options_rewriting_the_css_change_the_scope_id() {
+        let css = r#"css = ".card { margin-left: 0; }""#;
+        let plain = stylesheet_scope_id(css, "card");
+        let mut ids = vec![plain.clone()];
+        for option in [
+            "strategy = \"attribute\"",
+            "wrapper = false",
+            "dir = \"rtl\"",
+            "flatten",
+            "keep_comments",
+            "dedupe",
+            "scope_keyframes",
+            "scope_vars",
+        ] {
+            let id = stylesheet_scope_id(&format!("{}, {}", css, option), "card");
+            assert!(!ids.contains(&id), "{} gives {}", option, id);
+            ids.push(id);
+        }
+        for option in ["element = \"li\"", "classes", "inject = \"mount\""] {
+            let id = stylesheet_scope_id(&format!("{}, {}", css, option), "card");
+            assert_eq!(id, plain, "{}", option);
+        }
+    }
+
+    #[test]
+    fn themes_change_the_scope_id() {
+        let css = r#"css = ".a {} /* @theme dark */ .b {} /* @endtheme */ /* @theme light */ .c {} /* @endtheme */""#;
+        let dark = stylesheet_scope_id(&format!("{}, theme = \"dark\"", css), "card");
+        let light = stylesheet_scope_id(&format!("{}, theme = \"light\"", css), "card");
+        assert_ne!(dark, light);
+    }
+
+    fn expanded(args: StylesArgs, func: ItemFn) -> String {
+        match expand(args, func) {
+            Ok(tokens) => tokens.to_string(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn error(args: StylesArgs, func: ItemFn) -> String {
+        match expand(args, func) {
+            Ok(tokens) => panic!("expanded to {}", tokens),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn embeds_the_scoped_css() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let tokens = expanded(syn::parse_quote!(css = ".card { color: red; }"), func);
+        assert!(
+            tokens.contains("< div class = \"carde8ee3103\" >"),
+            "{}",
+            tokens
+        );
+        // Bundled, the CSS is served by the app instead.
+        if cfg!(feature = "bundle") {
+            assert!(tokens.contains("LEPTOS_STYLES_BUNDLE"), "{}", tokens);
+            assert!(!tokens.contains("inline_style"), "{}", tokens);
+        } else {
+            assert!(tokens.contains(".carde8ee3103 .card"), "{}", tokens);
+            assert!(tokens.contains("inline_style"), "{}", tokens);
+            assert!(
+                tokens.contains("data - style - id = \"carde8ee3103\""),
+                "{}",
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_the_wrapper_with_a_class_and_no_id() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let tokens = expanded(syn::parse_quote!(css = ".card { color: red; }"), func);
+        assert!(
+            tokens.contains("< div class = \"carde8ee3103\" >"),
+            "{}",
+            tokens
+        );
+        assert!(!tokens.contains("< div id ="), "{}", tokens);
+    }
+
+    #[test]
+    fn names_the_missing_file_of_several() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let args = syn::parse_quote!("../tests/card.css", "../tests/missing.css");
+        let error = error(args, func);
+        assert!(
+            error.starts_with("stylesheet `../tests/missing.css` not found"),
+            "{}",
+            error
+        );
+    }
+
+    fn module_error(args: ModuleArgs, module: syn::ItemMod) -> String {
+        match expand_module(args, module) {
+            Ok(tokens) => panic!("expanded to {}", tokens),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn module_names_the_component_missing_its_stylesheet() {
+        let module: syn::ItemMod = syn::parse_quote! {
+            mod ui {
+                #[component]
+                pub fn PrimaryButton() -> impl IntoView { view! { <button/> } }
+            }
+        };
+        let message = module_error(syn::parse_quote!(), module.clone());
+        assert!(
+            message.starts_with(
+                "no stylesheet for `PrimaryButton`: expected `primary_button.css` relative to the source file"
+            ),
+            "{}",
+            message
+        );
+        let message = module_error(syn::parse_quote!(root = "manifest"), module);
+        assert!(
+            message.starts_with(
+                "no stylesheet for `PrimaryButton`: expected `primary_button.css` relative to CARGO_MANIFEST_DIR"
+            ),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn attribute_strategy_marks_the_elements_with_the_attribute() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let args = syn::parse_quote!(css = ".card { color: red; }", strategy = "attribute");
+        let tokens = expanded(args, func);
+        let marker = "data - scope - cardc0e891de = \"\"";
+        assert!(
+            tokens.contains(&format!("< div {} >", marker)),
+            "{}",
+            tokens
+        );
+        assert!(
+            tokens.contains(&format!("< p {} class", marker)),
+            "{}",
+            tokens
+        );
+    }
+
+    #[test]
+    fn classes_module_has_a_constant_per_class_only() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="btn-primary">"..."</p> } }
+        };
+        let args = syn::parse_quote!(
+            css = ".btn-primary:hover, .card > p { color: red; }",
+            classes
+        );
+        let tokens = expanded(args, func);
+        assert!(tokens.contains("mod card_classes"), "{}", tokens);
+        for constant in [
+            "BTN_PRIMARY : & str = \"btn-primary\"",
+            "CARD : & str = \"card\"",
+        ] {
+            assert!(tokens.contains(constant), "{}", tokens);
+        }
+        assert!(!tokens.contains("const HOVER"), "{}", tokens);
+        assert!(!tokens.contains("const P "), "{}", tokens);
+    }
+
+    // Bundled, no stylesheet is injected at all.
+    #[cfg(not(feature = "bundle"))]
+    #[test]
+    fn hydration_looks_for_the_stylesheet_already_rendered() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let tokens = expanded(syn::parse_quote!(css = ".card { color: red; }"), func);
+        let query = "query_selector (\"head > style[data-style-id=\\\"carde8ee3103\\\"]\")";
+        assert!(tokens.contains(query), "{}", tokens);
+    }
+
+    #[test]
+    fn each_variant_is_gated_by_its_feature() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let args = syn::parse_quote!(
+            "../tests/card.css",
+            features(
+                dark = "../tests/base.css",
+                contrast = "../tests/variants.css"
+            )
+        );
+        let tokens = expanded(args, func);
+        let gates = [
+            "# [cfg (all (feature = \"dark\" , not (any ())))]",
+            "# [cfg (all (feature = \"contrast\" , not (any (feature = \"dark\"))))]",
+            "# [cfg (not (any (feature = \"dark\" , feature = \"contrast\")))]",
+        ];
+        let starts: Vec<usize> = gates
+            .iter()
+            .map(|gate| tokens.find(gate).unwrap_or_else(|| panic!("{}", tokens)))
+            .collect();
+        assert!(
+            starts.windows(2).all(|pair| pair[0] < pair[1]),
+            "{}",
+            tokens
+        );
+        // Each variant embeds its own stylesheet only.
+        if !cfg!(feature = "bundle") {
+            let variant =
+                |i: usize| &tokens[starts[i]..*starts.get(i + 1).unwrap_or(&tokens.len())];
+            assert!(variant(0).contains(".button { padding") && !variant(0).contains(" .card"));
+            assert!(variant(1).contains(".button.primary") && !variant(1).contains(" .card"));
+            assert!(variant(2).contains(" .card {") && !variant(2).contains(".button"));
+        }
+    }
+
+    #[test]
+    fn empty_stylesheets_are_an_error_unless_allowed() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        assert_eq!(
+            error(syn::parse_quote!(css = " /* soon */ "), func.clone()),
+            "inline `css` contains no CSS; write some, or add `allow_empty` to render the component unstyled"
+        );
+        let tokens = expanded(syn::parse_quote!(css = " /* soon */ ", allow_empty), func);
+        assert!(!tokens.contains("< div"), "{}", tokens);
+        assert!(!tokens.contains("data-style-id"), "{}", tokens);
+        assert!(tokens.contains("< p class = \"card\" >"), "{}", tokens);
+    }
+
+    #[test]
+    fn unknown_themes_are_an_error() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="card">"..."</p> } }
+        };
+        let css = ".a {} /* @theme dark */ .b {} /* @endtheme */ /* @theme light */ .c {} /* @endtheme */";
+        assert_eq!(
+            error(syn::parse_quote!(css = #css, theme = "blue"), func.clone()),
+            "unknown theme `blue`; the stylesheet defines `dark`, `light`"
+        );
+        assert_eq!(
+            error(syn::parse_quote!(css = ".a {}", theme = "blue"), func),
+            "the stylesheet has no `/* @theme <name> */` sections to choose from"
+        );
+    }
+
+    #[test]
+    fn warns_about_selectors_that_can_never_match() {
+        let func: ItemFn = syn::parse_quote! {
+            fn Card() -> impl IntoView { view! { <p class="title">"..."</p> } }
+        };
+        let message = "`body .title` in inline `css` at line 2 can never match, as scoping puts it inside the component, where there is no `html`, `body` or `:root`; wrap the page-level part in `:global(...)`";
+        let tokens = expanded(
+            syn::parse_quote!(css = ".card {}\nbody .title { color: red; }"),
+            func.clone(),
+        );
+        assert!(
+            tokens.contains(&format!(
+                "# [deprecated (note = {:?})] fn selector_never_matches () {{ }} selector_never_matches () ;",
+                message
+            )),
+            "{}",
+            tokens
+        );
+        // Global styles are not scoped, so `body` is fine there.
+        let tokens = expanded(
+            syn::parse_quote!(css = "body .title { color: red; }", scope = false),
+            func,
+        );
+        assert!(!tokens.contains("selector_never_matches"), "{}", tokens);
+    }
+
+    #[test]
+    fn module_scopes_each_component_with_its_stylesheet() {
+        let module: syn::ItemMod = syn::parse_quote! {
+            mod ui {
+                #[component]
+                pub fn Card() -> impl IntoView { view! { <p class="card"/> } }
+                #[component]
+                pub fn Badge() -> impl IntoView { view! { <span class="card"/> } }
+                #[component]
+                #[styles(css = ".own {}")]
+                pub fn Own() -> impl IntoView { view! { <p class="own"/> } }
+                fn helper() {}
+            }
+        };
+        let tokens = match expand_module(syn::parse_quote!(css = ".card { color: red; }"), module) {
+            Ok(tokens) => tokens.to_string(),
+            Err(e) => panic!("{}", e),
+        };
+        let css = r#"css = ".card { color: red; }""#;
+        for name in ["card", "badge"] {
+            let id = stylesheet_scope_id(css, name);
+            assert!(
+                tokens.contains(&format!("< div class = \"{}\" >", id)),
+                "{}",
+                tokens
+            );
+            if !cfg!(feature = "bundle") {
+                assert!(tokens.contains(&format!(".{} .card", id)), "{}", tokens);
+            }
+        }
+        // Components with their own `#[styles]` are left to it.
+        assert!(
+            tokens.contains("# [styles (css = \".own {}\")]"),
+            "{}",
+            tokens
+        );
+        assert!(tokens.contains("fn helper () { }"), "{}", tokens);
+    }
+}
@@ -0,0 +1,420 @@
+//! Reading the CSS a component is styled with.
+
+use crate::args::{PathRoot, Source};
+use crate::css;
+use proc_macro2::Span;
+use std::path::{Path, PathBuf};
+use syn::LitStr;
+
+/// The raw CSS of a component, in the order it was listed.
+pub(crate) struct LoadedStylesheet {
+    /// Human-readable prefix of the scope ID, always a valid CSS identifier.
+    pub(crate) name: String,
+    pub(crate) parts: Vec<StylesheetPart>,
+}
+
+/// A single file (or inline snippet) making up a component stylesheet.
+pub(crate) struct StylesheetPart {
+    /// The path as written in the attribute, empty for inline CSS.
+    pub(crate) path: String,
+    /// Absolute paths of the files this part was read from, including the
+    /// files it imports.
+    pub(crate) tracked_paths: Vec<String>,
+    pub(crate) source: String,
+    pub(crate) span: Span,
+}
+
+impl StylesheetPart {
+    /// Describes where the CSS came from, for diagnostics.
+    pub(crate) fn origin(&self) -> String {
+        if self.path.is_empty() {
+            "inline `css`".to_string()
+        } else {
+            format!("`{}`", self.path)
+        }
+    }
+}
+
+/// Loads the CSS of `source`, with paths relative to `root`. Inline CSS has no
+/// file name to derive the scope ID from, so `inline_name` is used instead.
+pub(crate) fn load_stylesheet(
+    source: &Source,
+    root: PathRoot,
+    inline_name: &str,
+) -> syn::Result<LoadedStylesheet> {
+    match source {
+        Source::Files(paths) => {
+            let stem = Path::new(&paths[0].value())
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = identifier(&stem);
+            let parts = paths
+                .iter()
+                .map(|path| load_file(path, root))
+                .collect::<syn::Result<_>>()?;
+            Ok(LoadedStylesheet { name, parts })
+        }
+        Source::Inline(css_lit) => {
+            let mut imports = Imports::new(css_lit);
+            let base = base_dir(root, css_lit.span());
+            let source = imports.inline(&css_lit.value(), &base, "inline `css`")?;
+            Ok(LoadedStylesheet {
+                name: identifier(inline_name),
+                parts: vec![StylesheetPart {
+                    path: String::new(),
+                    tracked_paths: imports.tracked,
+                    source,
+                    span: css_lit.span(),
+                }],
+            })
+        }
+    }
+}
+
+/// Turns `name` into something usable as a class name, attribute name and HTML
+/// id: characters other than ASCII letters, digits, `_` and `-` become `_`,
+/// and a leading digit or dash gets a `_` in front. `2col` becomes `_2col` and
+/// `my card` becomes `my_card`; a name with nothing usable left becomes
+/// `component`.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            out.push(c);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    let out = out.trim_matches('_');
+    if out.is_empty() {
+        "component".to_string()
+    } else if out.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        format!("_{}", out)
+    } else {
+        out.to_string()
+    }
+}
+
+/// The file a stylesheet path written in the attribute refers to.
+pub(crate) fn resolve(path_lit: &LitStr, root: PathRoot) -> PathBuf {
+    base_dir(root, path_lit.span()).join(path_lit.value())
+}
+
+fn load_file(path_lit: &LitStr, root: PathRoot) -> syn::Result<StylesheetPart> {
+    let path_str = path_lit.value();
+    let full_path = resolve(path_lit, root);
+    if std::fs::metadata(&full_path).is_err() {
+        let mut message = format!(
+            "stylesheet `{}` not found: no file at `{}` (paths are relative to {})",
+            path_str,
+            full_path.display(),
+            root.describe()
+        );
+        if root == PathRoot::File && manifest_dir().join(&path_str).is_file() {
+            message.push_str(
+                "; the path exists relative to CARGO_MANIFEST_DIR, add `root = \"manifest\"` to use it",
+            );
+        }
+        return Err(syn::Error::new(path_lit.span(), message));
+    }
+    let css = std::fs::read_to_string(&full_path).map_err(|e| {
+        syn::Error::new(
+            path_lit.span(),
+            format!("failed to read `{}`: {}", path_str, e),
+        )
+    })?;
+
+    let is_sass = matches!(
+        full_path.extension().and_then(|ext| ext.to_str()),
+        Some("scss" | "sass")
+    );
+    let css = if is_sass {
+        compile_sass(&full_path, path_lit)?
+    } else {
+        css
+    };
+
+    let mut imports = Imports::new(path_lit);
+    imports
+        .tracked
+        .push(full_path.to_string_lossy().into_owned());
+    if let Ok(canonical) = full_path.canonicalize() {
+        imports.stack.push(canonical);
+    }
+    let base = full_path.parent().unwrap_or(Path::new(""));
+    let css = imports.inline(&css, base, &format!("`{}`", path_str))?;
+
+    Ok(StylesheetPart {
+        path: path_str,
+        tracked_paths: imports.tracked,
+        source: css,
+        span: path_lit.span(),
+    })
+}
+
+/// The directory paths written at `span` are relative to.
+fn base_dir(root: PathRoot, span: Span) -> PathBuf {
+    let file = match root {
+        PathRoot::File if proc_macro::is_available() => span.unwrap().local_file(),
+        _ => None,
+    };
+    // Spans without a file on disk, such as those of rust-analyzer or of unit
+    // tests, fall back to the manifest directory.
+    match file.as_deref().and_then(Path::parent) {
+        Some(dir) if dir.is_absolute() => dir.to_path_buf(),
+        Some(dir) => std::env::current_dir().unwrap_or_default().join(dir),
+        None => manifest_dir(),
+    }
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default())
+}
+
+/// Splices local `@import`d files into a stylesheet.
+struct Imports<'a> {
+    /// The attribute literal diagnostics are reported on.
+    lit: &'a LitStr,
+    /// Files currently being inlined, outermost first, to detect cycles.
+    stack: Vec<PathBuf>,
+    tracked: Vec<String>,
+    /// `@import`s of other origins, which must stay at the top of the output.
+    remote: Vec<String>,
+}
+
+impl<'a> Imports<'a> {
+    fn new(lit: &'a LitStr) -> Self {
+        Imports {
+            lit,
+            stack: Vec::new(),
+            tracked: Vec::new(),
+            remote: Vec::new(),
+        }
+    }
+
+    /// Replaces every local `@import` of `css` with the contents of the
+    /// imported file, resolved against `base`, recursively. Imports carrying a
+    /// media query are wrapped in a matching `@media` block; imports of other
+    /// origins are kept, and moved to the top so they still precede every rule.
+    fn inline(&mut self, css: &str, base: &Path, origin: &str) -> syn::Result<String> {
+        let inlined = self.splice(css, base, origin)?;
+        let mut out = std::mem::take(&mut self.remote).join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&inlined);
+        Ok(out)
+    }
+
+    fn splice(&mut self, css: &str, base: &Path, origin: &str) -> syn::Result<String> {
+        let imports = css::imports(css)
+            .map_err(|e| syn::Error::new(self.lit.span(), e.describe(css, origin)))?;
+
+        let mut out = String::with_capacity(css.len());
+        let mut last = 0;
+        for import in &imports {
+            out.push_str(&css[last..import.range.start]);
+            last = import.range.end;
+            if import.is_remote() {
+                self.remote.push(css[import.range.clone()].to_string());
+                continue;
+            }
+
+            let path = base.join(&import.url);
+            let canonical = path.canonicalize().map_err(|_| {
+                self.error(format!(
+                    "`@import \"{}\"` in {}: no file at `{}`",
+                    import.url,
+                    origin,
+                    path.display()
+                ))
+            })?;
+            if let Some(start) = self.stack.iter().position(|file| *file == canonical) {
+                let cycle = self.stack[start..]
+                    .iter()
+                    .chain([&canonical])
+                    .map(|file| file.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(self.error(format!("`@import` cycle: {}", cycle)));
+            }
+            let conditions = &import.conditions;
+            if conditions.starts_with("layer") || conditions.starts_with("supports(") {
+                return Err(self.error(format!(
+                    "`@import \"{}\"` in {}: only media queries are supported as import conditions",
+                    import.url, origin
+                )));
+            }
+
+            let source = std::fs::read_to_string(&canonical).map_err(|e| {
+                self.error(format!("failed to read `{}`: {}", canonical.display(), e))
+            })?;
+            self.tracked.push(canonical.to_string_lossy().into_owned());
+            self.stack.push(canonical.clone());
+            let inner_base = canonical.parent().unwrap_or(Path::new(""));
+            let inlined = self.splice(&source, inner_base, &format!("`{}`", import.url))?;
+            self.stack.pop();
+
+            if conditions.is_empty() {
+                out.push_str(&inlined);
+            } else {
+                out.push_str(&format!("@media {} {{\n{}\n}}", conditions, inlined));
+            }
+        }
+        out.push_str(&css[last..]);
+        Ok(out)
+    }
+
+    fn error(&self, message: String) -> syn::Error {
+        syn::Error::new(self.lit.span(), message)
+    }
+}
+
+/// Compiles a `.scss` or `.sass` file to plain CSS.
+#[cfg(feature = "sass")]
+fn compile_sass(full_path: &Path, path_lit: &LitStr) -> syn::Result<String> {
+    grass::from_path(full_path, &grass::Options::default()).map_err(|e| {
+        syn::Error::new(
+            path_lit.span(),
+            format!("failed to compile `{}`: {}", path_lit.value(), e),
+        )
+    })
+}
+
+#[cfg(not(feature = "sass"))]
+fn compile_sass(_full_path: &Path, path_lit: &LitStr) -> syn::Result<String> {
+    Err(syn::Error::new(
+        path_lit.span(),
+        format!(
+            "`{}` is a Sass stylesheet; enable the `sass` feature of leptos_styles to compile it",
+            path_lit.value()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a directory of their own and returns it.
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("leptos_styles-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn inline(dir: &Path, css: &str) -> Result<String, String> {
+        let lit: LitStr = syn::parse_quote!("card.css");
+        let mut imports = Imports::new(&lit);
+        let inlined = imports
+            .inline(css, dir, "`card.css`")
+            .map_err(|e| e.to_string());
+        let _ = std::fs::remove_dir_all(dir);
+        inlined
+    }
+
+    #[test]
+    fn inlines_nested_imports_relative_to_their_file() {
+        let dir = fixture(
+            "nested",
+            &[
+                (
+                    "base/base.css",
+                    "@import \"reset.css\";\n.base { color: red; }",
+                ),
+                ("base/reset.css", "* { margin: 0; }"),
+            ],
+        );
+        assert_eq!(
+            inline(&dir, "@import \"base/base.css\";\n.card { color: blue; }").unwrap(),
+            "* { margin: 0; }\n.base { color: red; }\n.card { color: blue; }"
+        );
+    }
+
+    #[test]
+    fn wraps_imports_with_a_media_query() {
+        let dir = fixture("media", &[("print.css", ".card { color: black; }")]);
+        assert_eq!(
+            inline(&dir, "@import \"print.css\" print;").unwrap(),
+            "@media print {\n.card { color: black; }\n}"
+        );
+    }
+
+    #[test]
+    fn hoists_remote_imports() {
+        let dir = fixture(
+            "remote",
+            &[(
+                "base.css",
+                "@import url(\"https://example.com/a.css\");\n.base {}",
+            )],
+        );
+        assert_eq!(
+            inline(&dir, ".card {}\n@import \"base.css\";").unwrap(),
+            "@import url(\"https://example.com/a.css\");\n.card {}\n\n.base {}"
+        );
+    }
+
+    #[test]
+    fn reports_import_cycles() {
+        let dir = fixture(
+            "cycle",
+            &[
+                ("a.css", "@import \"b.css\";"),
+                ("b.css", "@import \"a.css\";"),
+            ],
+        );
+        let a = dir.join("a.css").canonicalize().unwrap();
+        let b = dir.join("b.css").canonicalize().unwrap();
+        assert_eq!(
+            inline(&dir, "@import \"a.css\";").unwrap_err(),
+            format!(
+                "`@import` cycle: {} -> {} -> {}",
+                a.display(),
+                b.display(),
+                a.display()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_missing_imports() {
+        let dir = fixture("missing", &[]);
+        assert_eq!(
+            inline(&dir, "@import \"gone.css\";").unwrap_err(),
+            format!(
+                "`@import \"gone.css\"` in `card.css`: no file at `{}`",
+                dir.join("gone.css").display()
+            )
+        );
+    }
+
+    #[test]
+    fn identifiers_are_valid_class_names() {
+        let cases = [
+            ("card", "card"),
+            ("2col", "_2col"),
+            ("my component", "my_component"),
+            ("über", "ber"),
+            ("-dark", "_-dark"),
+            ("", "component"),
+            ("@@", "component"),
+        ];
+        for (name, expected) in cases {
+            let identifier = identifier(name);
+            assert_eq!(identifier, expected);
+            let mut chars = identifier.chars();
+            assert!(chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_'));
+            assert!(chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Compile-time minification of scoped CSS.
+
+use crate::css;
+
+/// Strips comments, collapses whitespace and drops the last semicolon of each
+/// block.
+///
+/// Strings and `url(...)` arguments are copied verbatim. Whitespace is only
+/// removed where it can never be significant, so `calc(100% - 2px)` keeps its
+/// spaces and `.a :hover` stays distinct from `.a:hover`.
+pub(crate) fn minify(css: &str) -> String {
+    let chars: Vec<char> = css.chars().collect();
+    let mut out = String::with_capacity(css.len());
+    let mut depth = 0usize;
+    let mut pending_space = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i = find(&chars, i + 2, "*/").map_or(chars.len(), |end| end + 2);
+            continue;
+        }
+        if c.is_whitespace() {
+            pending_space = true;
+            i += 1;
+            continue;
+        }
+
+        if pending_space {
+            if let Some(prev) = out.chars().last() {
+                if needs_space(prev, c, depth) {
+                    out.push(' ');
+                }
+            }
+            pending_space = false;
+        }
+
+        match c {
+            '"' | '\'' => {
+                let end = string_end(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '}' if out.ends_with(';') => {
+                out.pop();
+            }
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        out.push(c);
+        i += 1;
+
+        if c == '(' && css::ends_with_ignore_case(&out, "url(") {
+            let end = url_end(&chars, i);
+            out.extend(&chars[i..end]);
+            i = end;
+        }
+    }
+
+    out
+}
+
+/// Whether the whitespace between `prev` and `next` must be kept.
+fn needs_space(prev: char, next: char, depth: usize) -> bool {
+    let separator = |c: char| {
+        matches!(c, '{' | '}' | ';' | ',') || (depth == 0 && matches!(c, '>' | '~' | '+'))
+    };
+    !(separator(prev) || separator(next) || matches!(prev, ':' | '(') || next == ')')
+}
+
+/// Returns the index just past the string starting at `start`.
+fn string_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Returns the index of the `)` closing a `url(` whose argument starts at `start`.
+fn url_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '"' | '\'' => i = string_end(chars, i),
+            ')' => return i,
+            '\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+fn find(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    (from..chars.len()).find(|&i| chars[i..].starts_with(&pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_url_arguments_whatever_their_case() {
+        assert_eq!(
+            minify(".a { background: URL( a  b.png ); }"),
+            ".a{background:URL( a  b.png )}"
+        );
+        assert_eq!(
+            minify(".a { background: url( x.png ) }"),
+            ".a{background:url( x.png )}"
+        );
+    }
+
+    #[test]
+    fn handles_non_ascii_before_a_parenthesis() {
+        assert_eq!(minify(".é { width: calc(1px) }"), ".é{width:calc(1px)}");
+    }
+
+    #[test]
+    fn minifies_a_stylesheet_keeping_what_is_significant() {
+        let css = "/* card */\n.card  >  .title ,\n.card :hover {\n  content: \"a  ;  b\";\n  background: url( a  b.png );\n  width: calc(100% - 2px);\n}\n\n@media (min-width: 600px) {\n  .card { margin: 0 auto; }\n}\n";
+        assert_eq!(
+            minify(css),
+            ".card>.title,.card :hover{content:\"a  ;  b\";background:url( a  b.png );width:calc(100% - 2px)}@media (min-width:600px){.card{margin:0 auto}}"
+        );
+    }
+}
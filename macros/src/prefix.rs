@@ -0,0 +1,140 @@
+//! Compile-time vendor prefixing of scoped CSS.
+
+use crate::css::{self, Item, Stylesheet};
+
+/// Properties that still need vendor-prefixed copies in some browsers, with
+/// the prefixes to add.
+const PROPERTIES: &[(&str, &[&str])] = &[
+    ("user-select", &["-webkit-", "-moz-", "-ms-"]),
+    ("appearance", &["-webkit-", "-moz-"]),
+    ("backdrop-filter", &["-webkit-"]),
+    ("text-size-adjust", &["-webkit-", "-moz-"]),
+    ("hyphens", &["-webkit-"]),
+    ("mask", &["-webkit-"]),
+    ("mask-image", &["-webkit-"]),
+    ("flex", &["-webkit-"]),
+    ("flex-basis", &["-webkit-"]),
+    ("flex-direction", &["-webkit-"]),
+    ("flex-flow", &["-webkit-"]),
+    ("flex-grow", &["-webkit-"]),
+    ("flex-shrink", &["-webkit-"]),
+    ("flex-wrap", &["-webkit-"]),
+    ("order", &["-webkit-"]),
+    ("align-items", &["-webkit-"]),
+    ("align-self", &["-webkit-"]),
+    ("align-content", &["-webkit-"]),
+    ("justify-content", &["-webkit-"]),
+];
+
+/// Values that need a prefixed fallback, keyed by property.
+const VALUES: &[(&str, &str, &str)] = &[
+    ("display", "flex", "-webkit-flex"),
+    ("display", "inline-flex", "-webkit-inline-flex"),
+    ("position", "sticky", "-webkit-sticky"),
+];
+
+/// Adds vendor-prefixed copies of the declarations listed in [`PROPERTIES`]
+/// and [`VALUES`], each placed just before the declaration it duplicates so
+/// the standard form still wins where it is supported.
+///
+/// This runs on already scoped CSS, so the copies share the selector, and the
+/// scope, of the original declaration. Prefixed forms the stylesheet already
+/// spells out are not added twice.
+pub(crate) fn prefix(css: &str) -> String {
+    match Stylesheet::parse(css) {
+        Ok(mut sheet) => {
+            prefix_items(&mut sheet.items);
+            sheet.to_string()
+        }
+        Err(_) => css.to_string(),
+    }
+}
+
+fn prefix_items(items: &mut [Item]) {
+    for item in items {
+        match item {
+            Item::Rule { body, .. } => *body = prefix_declarations(body),
+            Item::Group { items, .. } => prefix_items(items),
+            Item::AtRule { .. } | Item::Comment(_) => {}
+        }
+    }
+}
+
+/// Inserts the prefixed copies into a raw declaration block, keeping the rest
+/// of its text as written.
+fn prefix_declarations(body: &str) -> String {
+    let declarations: Vec<(usize, &str, &str)> = css::split_top_level(body, ';')
+        .into_iter()
+        .filter_map(|declaration| {
+            let (name, value) = declaration.split_once(':')?;
+            let offset = declaration.as_ptr() as usize - body.as_ptr() as usize;
+            Some((offset, name.trim(), value.trim()))
+        })
+        .collect();
+    let declared = |name: &str, value: Option<&str>| {
+        declarations.iter().any(|&(_, n, v)| {
+            n.eq_ignore_ascii_case(name) && value.is_none_or(|value| v.eq_ignore_ascii_case(value))
+        })
+    };
+
+    let mut out = String::with_capacity(body.len());
+    let mut last = 0;
+    for &(offset, name, value) in &declarations {
+        let lower = name.to_ascii_lowercase();
+        let mut copies = Vec::new();
+        if let Some((_, prefixes)) = PROPERTIES.iter().find(|(property, _)| *property == lower) {
+            for prefix in *prefixes {
+                let prefixed = format!("{prefix}{name}");
+                if !declared(&prefixed, None) {
+                    copies.push(format!("{prefixed}: {value}"));
+                }
+            }
+        }
+        for &(_, _, prefixed) in VALUES.iter().filter(|(property, standard, _)| {
+            *property == lower && value.eq_ignore_ascii_case(standard)
+        }) {
+            if !declared(name, Some(prefixed)) {
+                copies.push(format!("{name}: {prefixed}"));
+            }
+        }
+        if copies.is_empty() {
+            continue;
+        }
+
+        out.push_str(&body[last..offset]);
+        for copy in copies {
+            out.push_str(&copy);
+            out.push_str("; ");
+        }
+        last = offset;
+    }
+    out.push_str(&body[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_prefixed_copies_before_each_declaration() {
+        assert_eq!(
+            prefix(".a { display: flex; flex: 1; color: red; }"),
+            ".a { display: -webkit-flex; display: flex; -webkit-flex: 1; flex: 1; color: red; }"
+        );
+        assert_eq!(
+            prefix(".a { user-select: none; appearance: none; backdrop-filter: blur(2px); }"),
+            ".a { -webkit-user-select: none; -moz-user-select: none; -ms-user-select: none; user-select: none; \
+             -webkit-appearance: none; -moz-appearance: none; appearance: none; \
+             -webkit-backdrop-filter: blur(2px); backdrop-filter: blur(2px); }"
+        );
+    }
+
+    #[test]
+    fn keeps_the_prefixes_already_there() {
+        assert_eq!(
+            prefix("@media (min-width: 1px) {\n.b { -webkit-user-select: text; user-select: text; }\n}"),
+            "@media (min-width: 1px) {\n.b { -webkit-user-select: text; -moz-user-select: text; -ms-user-select: text; user-select: text; }\n}"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Mirroring of scoped CSS for right-to-left layouts.
+
+use crate::css::{self, Item, Stylesheet};
+
+/// Properties swapped with their mirror image.
+const PROPERTIES: &[(&str, &str)] = &[
+    ("left", "right"),
+    ("margin-left", "margin-right"),
+    ("padding-left", "padding-right"),
+    ("border-left", "border-right"),
+    ("border-left-color", "border-right-color"),
+    ("border-left-style", "border-right-style"),
+    ("border-left-width", "border-right-width"),
+    ("border-top-left-radius", "border-top-right-radius"),
+    ("border-bottom-left-radius", "border-bottom-right-radius"),
+];
+
+/// Properties whose `left` and `right` keywords are swapped.
+const KEYWORDS: &[&str] = &["float", "clear", "text-align"];
+
+/// Shorthands taking one to four values for the top, right, bottom and left
+/// sides, whose right and left values are swapped.
+const SIDES: &[&str] = &[
+    "margin",
+    "padding",
+    "border-width",
+    "border-style",
+    "border-color",
+    "inset",
+];
+
+/// Flips the horizontal direction of `css`, as if it had been written for a
+/// right-to-left layout: `margin-left` becomes `margin-right`, `float: left`
+/// becomes `float: right`, `padding: 0 1px 0 2px` becomes `padding: 0 2px 0
+/// 1px`, and so on.
+///
+/// Logical properties such as `margin-inline-start` already follow the
+/// writing direction and are left alone, as are custom properties and values
+/// that are not plain keywords, so nothing inside a `url()` or a `var()` is
+/// changed. Rules nested in a rule are not mirrored unless `flatten` made
+/// them top-level.
+pub(crate) fn mirror(css: &str) -> String {
+    match Stylesheet::parse(css) {
+        Ok(mut sheet) => {
+            mirror_items(&mut sheet.items);
+            sheet.to_string()
+        }
+        Err(_) => css.to_string(),
+    }
+}
+
+fn mirror_items(items: &mut [Item]) {
+    for item in items {
+        match item {
+            Item::Rule { body, .. } => *body = mirror_declarations(body),
+            Item::Group { items, .. } => mirror_items(items),
+            Item::AtRule { .. } | Item::Comment(_) => {}
+        }
+    }
+}
+
+/// Mirrors the declarations of a raw declaration block, keeping the rest of
+/// its text as written.
+fn mirror_declarations(body: &str) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut last = 0;
+    for declaration in css::split_top_level(body, ';') {
+        let Some(mirrored) = mirror_declaration(declaration) else {
+            continue;
+        };
+        let offset = declaration.as_ptr() as usize - body.as_ptr() as usize;
+        out.push_str(&body[last..offset]);
+        out.push_str(&mirrored);
+        last = offset + declaration.len();
+    }
+    out.push_str(&body[last..]);
+    out
+}
+
+/// Returns the mirrored form of `name: value`, or `None` if it does not
+/// depend on the direction.
+fn mirror_declaration(declaration: &str) -> Option<String> {
+    if declaration.contains('{') {
+        return None;
+    }
+    let (name, value) = declaration.split_once(':')?;
+    let name = name.trim();
+    let lower = name.to_ascii_lowercase();
+    if lower.starts_with("--") {
+        return None;
+    }
+    let (value, important) = match value.trim().rsplit_once('!') {
+        Some((value, flag)) if flag.trim().eq_ignore_ascii_case("important") => {
+            (value.trim(), " !important")
+        }
+        _ => (value.trim(), ""),
+    };
+
+    let mirrored_name = PROPERTIES.iter().find_map(|&(a, b)| match lower.as_str() {
+        n if n == a => Some(b),
+        n if n == b => Some(a),
+        _ => None,
+    });
+    let mirrored_value = if KEYWORDS.contains(&lower.as_str()) {
+        match value.to_ascii_lowercase().as_str() {
+            "left" => Some("right".to_string()),
+            "right" => Some("left".to_string()),
+            _ => None,
+        }
+    } else if SIDES.contains(&lower.as_str()) {
+        mirror_sides(value)
+    } else if lower == "border-radius" {
+        mirror_radius(value)
+    } else {
+        None
+    };
+    if mirrored_name.is_none() && mirrored_value.is_none() {
+        return None;
+    }
+    Some(format!(
+        "{}: {}{}",
+        mirrored_name.unwrap_or(name),
+        mirrored_value.as_deref().unwrap_or(value),
+        important
+    ))
+}
+
+/// Swaps the right and left values of a box shorthand, which only differ when
+/// all four sides are given.
+fn mirror_sides(value: &str) -> Option<String> {
+    let value = value.replace(char::is_whitespace, " ");
+    match css::split_top_level(&value, ' ').as_slice() {
+        [top, right, bottom, left] if right != left && !value.contains("var(") => {
+            Some(format!("{top} {left} {bottom} {right}"))
+        }
+        _ => None,
+    }
+}
+
+/// Mirrors the corner radii of `border-radius`, on both sides of a `/`.
+fn mirror_radius(value: &str) -> Option<String> {
+    let mirror_corners = |corners: &str| {
+        let values = css::split_top_level(corners, ' ');
+        if values.iter().any(|value| value.contains("var(")) {
+            return None;
+        }
+        match values.as_slice() {
+            [a, b, c, d] => Some(format!("{b} {a} {d} {c}")),
+            [a, b, c] => Some(format!("{b} {a} {b} {c}")),
+            [a, b] => Some(format!("{b} {a}")),
+            [a] => Some(a.to_string()),
+            _ => None,
+        }
+    };
+    let value = value.replace(char::is_whitespace, " ");
+    let mirrored = css::split_top_level(&value, '/')
+        .into_iter()
+        .map(mirror_corners)
+        .collect::<Option<Vec<_>>>()?
+        .join(" / ");
+    (mirrored != value).then_some(mirrored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_physical_properties_and_values() {
+        assert_eq!(
+            mirror(".a { margin-left: 4px; float: left; text-align: right; }"),
+            ".a { margin-right: 4px; float: right; text-align: left; }"
+        );
+    }
+
+    #[test]
+    fn leaves_logical_properties_urls_and_custom_properties_alone() {
+        let css = ".a { margin-inline-start: 4px; background: url(left.png); --left: 1px; left: var(--left); }";
+        assert_eq!(
+            mirror(css),
+            ".a { margin-inline-start: 4px; background: url(left.png); --left: 1px; right: var(--left); }"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Bookkeeping of the scope IDs generated while compiling a crate.
+//!
+//! A proc-macro crate is loaded once per compilation, so this only sees the
+//! components of the crate being built; scopes of other crates cannot be
+//! checked.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// A generated scope ID, with what it was derived from.
+struct Claim {
+    /// The exact bytes the ID was hashed from, or the stylesheet sharing a
+    /// fixed ID.
+    input: Vec<u8>,
+    /// The macro invocation making the claim.
+    site: String,
+    /// Where the CSS came from, for diagnostics.
+    origin: String,
+}
+
+static SCOPES: OnceLock<Mutex<HashMap<String, Claim>>> = OnceLock::new();
+
+/// Records that `id` was derived from `input` by the invocation at `site`, and
+/// returns the origin of the stylesheet that already claimed `id` if it was
+/// derived from a different input.
+///
+/// Identical inputs, such as two components sharing a stylesheet, map to the
+/// same ID on purpose and never collide. A claim from the same `site`, as when
+/// an IDE expands a macro again after its stylesheet was edited, replaces the
+/// previous one.
+pub(crate) fn claim(id: &str, input: Vec<u8>, site: String, origin: String) -> Result<(), String> {
+    let mut scopes = SCOPES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match scopes.get(id) {
+        Some(claim) if claim.input != input && claim.site != site => Err(claim.origin.clone()),
+        Some(claim) if claim.input == input => Ok(()),
+        _ => {
+            scopes.insert(
+                id.to_string(),
+                Claim {
+                    input,
+                    site,
+                    origin,
+                },
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim_at(id: &str, css: &str, site: &str) -> Result<(), String> {
+        claim(
+            id,
+            css.into(),
+            site.to_string(),
+            format!("{} at {}", css, site),
+        )
+    }
+
+    #[test]
+    fn different_stylesheets_cannot_share_an_id() {
+        claim_at("collision1", ".a {}", "card.rs:1").unwrap();
+        assert_eq!(
+            claim_at("collision1", ".b {}", "badge.rs:1"),
+            Err(".a {} at card.rs:1".to_string())
+        );
+    }
+
+    #[test]
+    fn a_stylesheet_can_claim_its_id_again() {
+        claim_at("shared1", ".a {}", "card.rs:1").unwrap();
+        claim_at("shared1", ".a {}", "card.rs:1").unwrap();
+        // Another component with the same stylesheet shares the scope.
+        claim_at("shared1", ".a {}", "badge.rs:1").unwrap();
+    }
+
+    #[test]
+    fn an_edited_stylesheet_replaces_its_claim() {
+        claim_at("edited1", ".a {}", "card.rs:1").unwrap();
+        claim_at("edited1", ".a { color: red; }", "card.rs:1").unwrap();
+        assert_eq!(
+            claim_at("edited1", ".a {}", "badge.rs:1"),
+            Err(".a { color: red; } at card.rs:1".to_string())
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Source maps relating scoped CSS back to the stylesheets it was made from.
+
+use crate::css;
+use crate::load::StylesheetPart;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns a `/*# sourceMappingURL=... */` comment for `css`, the scoped
+/// output of `parts`, with the map inlined as a data URL.
+///
+/// The map is coarse: each rule of the output points at the start of the rule
+/// it was generated from, which is enough for browser tools to show the right
+/// file and line. Scoping, prefixing and minifying keep the rules in order,
+/// so they are matched up by position; `None` is returned if they cannot be.
+pub(crate) fn source_map(css: &str, parts: &[StylesheetPart], flatten: bool) -> Option<String> {
+    let generated = css::rule_offsets(css, false).ok()?;
+    let mut original = Vec::with_capacity(generated.len());
+    for (index, part) in parts.iter().enumerate() {
+        for offset in css::rule_offsets(&part.source, flatten).ok()? {
+            original.push((index, position(&part.source, offset)));
+        }
+    }
+    if generated.len() != original.len() {
+        return None;
+    }
+
+    let mut mappings = String::new();
+    let mut line = 0;
+    // Every field but the generated column is relative to the previous
+    // segment of the whole map.
+    let (mut column, mut source, mut source_line, mut source_column) = (0, 0, 0, 0);
+    for (offset, (index, (to_line, to_column))) in generated.into_iter().zip(original) {
+        let (from_line, from_column) = position(css, offset);
+        if from_line > line {
+            for _ in line..from_line {
+                mappings.push(';');
+            }
+            line = from_line;
+            column = 0;
+        } else if !mappings.is_empty() && !mappings.ends_with(';') {
+            mappings.push(',');
+        }
+        vlq(&mut mappings, from_column - column);
+        vlq(&mut mappings, index as i64 - source);
+        vlq(&mut mappings, to_line - source_line);
+        vlq(&mut mappings, to_column - source_column);
+        (column, source, source_line, source_column) =
+            (from_column, index as i64, to_line, to_column);
+    }
+
+    let sources = parts
+        .iter()
+        .map(|part| match part.path.as_str() {
+            "" => json_string("inline.css"),
+            path => json_string(path),
+        })
+        .collect::<Vec<_>>();
+    let contents = parts
+        .iter()
+        .map(|part| json_string(&part.source))
+        .collect::<Vec<_>>();
+    let map = format!(
+        "{{\"version\":3,\"sources\":[{}],\"sourcesContent\":[{}],\"names\":[],\"mappings\":\"{}\"}}",
+        sources.join(","),
+        contents.join(","),
+        mappings
+    );
+    Some(format!(
+        "/*# sourceMappingURL=data:application/json;base64,{} */",
+        base64(map.as_bytes())
+    ))
+}
+
+/// Returns the zero-based line and column of `offset` in `text`, with columns
+/// counted in UTF-16 code units as browsers do.
+fn position(text: &str, offset: usize) -> (i64, i64) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count();
+    let column = before[line_start..].encode_utf16().count();
+    (line as i64, column as i64)
+}
+
+/// Appends `value` as a base64 VLQ, the number encoding of source maps.
+fn vlq(out: &mut String, value: i64) {
+    let mut rest = if value < 0 {
+        ((-value as u64) << 1) | 1
+    } else {
+        (value as u64) << 1
+    };
+    loop {
+        let mut digit = (rest & 0b11111) as usize;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i)) as usize & 0b111111] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(path: &str, source: &str) -> StylesheetPart {
+        StylesheetPart {
+            path: path.to_string(),
+            tracked_paths: Vec::new(),
+            source: source.to_string(),
+            span: proc_macro2::Span::call_site(),
+        }
+    }
+
+    #[test]
+    fn encodes_vlq_and_base64() {
+        let mut out = String::new();
+        for value in [0, 1, -1, 15, 16, -100] {
+            vlq(&mut out, value);
+        }
+        assert_eq!(out, "ACDegBpG");
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+    }
+
+    #[test]
+    fn maps_each_rule_to_the_one_it_came_from() {
+        let parts = [
+            part("a.css", ".a { color: red; }"),
+            part("b.css", "\n.b { color: \"blue\"; }"),
+        ];
+        let css = ".card .a { color: red; }\n.card .b { color: \"blue\"; }";
+        let map = "{\"version\":3,\"sources\":[\"a.css\",\"b.css\"],\
+                   \"sourcesContent\":[\".a { color: red; }\",\"\\n.b { color: \\\"blue\\\"; }\"],\
+                   \"names\":[],\"mappings\":\"AAAA;ACCA\"}";
+        assert_eq!(
+            source_map(css, &parts, false).unwrap(),
+            format!(
+                "/*# sourceMappingURL=data:application/json;base64,{} */",
+                base64(map.as_bytes())
+            )
+        );
+    }
+
+    #[test]
+    fn gives_up_when_the_rules_do_not_match() {
+        let parts = [part("", ".a { color: red; }")];
+        assert_eq!(source_map(".a {} .b {}", &parts, false), None);
+    }
+}
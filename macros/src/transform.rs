@@ -0,0 +1,228 @@
+//! The passes turning a component's CSS into the string that is embedded.
+//!
+//! Nothing here depends on the macro input, so every pass can be checked on
+//! plain strings, with [`Pipeline::run`] running them as `#[styles]` does, and
+//! [`scope_css`] as it does without options.
+
+use crate::args::Direction;
+use crate::css::{self, Locals, ParseError, Scope};
+#[cfg(feature = "minify")]
+use crate::minify;
+#[cfg(feature = "prefix")]
+use crate::prefix;
+use crate::rtl;
+
+/// Scopes `css` under the class `id` and runs the default passes over it,
+/// which is all `#[styles]` does to a single file without options.
+pub(crate) fn scope_css(css: &str, id: &str) -> Result<String, ParseError> {
+    let pipeline = Pipeline::new(Some(Scope::Class(id.to_string())));
+    pipeline.run(&[css]).map_err(|(_, e)| e)
+}
+
+/// The passes to run, and how.
+pub(crate) struct Pipeline {
+    /// The scope to add to every selector, or `None` to leave the CSS
+    /// unscoped.
+    pub(crate) scope: Option<Scope>,
+    /// Whether nested rules are flattened first.
+    pub(crate) flatten: bool,
+    /// The keyframes and custom properties to rename, with the suffix added
+    /// to their names.
+    pub(crate) locals: Option<(Locals, String)>,
+    pub(crate) keep_comments: bool,
+    /// Whether rules repeated verbatim are dropped.
+    pub(crate) dedupe: bool,
+    pub(crate) dir: Direction,
+}
+
+impl Pipeline {
+    /// The default passes, scoping the CSS to `scope`.
+    pub(crate) fn new(scope: Option<Scope>) -> Self {
+        Pipeline {
+            scope,
+            flatten: false,
+            locals: None,
+            keep_comments: false,
+            dedupe: false,
+            dir: Direction::Ltr,
+        }
+    }
+
+    /// Runs every pass over the files `sources`, joined in order, and returns
+    /// the CSS to embed. An error comes with the index of the file it is in,
+    /// and is positioned in that file.
+    pub(crate) fn run(&self, sources: &[&str]) -> Result<String, (usize, ParseError)> {
+        let parts = sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| self.part(source).map_err(|e| (index, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.finish(&parts.join("\n")))
+    }
+
+    /// Runs the passes that need one file at a time: flattening, renaming and
+    /// scoping. Errors are positioned in `source`.
+    fn part(&self, source: &str) -> Result<String, ParseError> {
+        let mut css = if self.flatten {
+            css::flatten_nesting(source)?
+        } else {
+            source.to_string()
+        };
+        if let Some((locals, suffix)) = &self.locals {
+            css = css::rename_locals(&css, locals, suffix)?;
+        }
+        match &self.scope {
+            Some(scope) => css::scope_stylesheet(&css, scope),
+            // Still parsed, so a broken file is reported.
+            None => css::Stylesheet::parse(&css).map(|_| css),
+        }
+    }
+
+    /// Runs the passes over the whole stylesheet, once its files have been
+    /// through [`Pipeline::part`] and joined.
+    fn finish(&self, css: &str) -> String {
+        let css = if self.dedupe {
+            css::dedupe_rules(css)
+        } else {
+            css.to_string()
+        };
+        let css = if self.keep_comments {
+            css
+        } else {
+            css::strip_comments(&css)
+        };
+        let css = match self.dir {
+            Direction::Ltr => css,
+            Direction::Rtl => rtl::mirror(&css),
+        };
+        #[cfg(feature = "prefix")]
+        let css = prefix::prefix(&css);
+        #[cfg(feature = "minify")]
+        let css = minify::minify(&css);
+        css
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collapses whitespace, and drops it next to punctuation along with the
+    /// optional last `;` of a block, as scoping and minifying may.
+    fn normalize(css: &str) -> String {
+        let mut out = css.split_whitespace().collect::<Vec<_>>().join(" ");
+        for punctuation in ["{", "}", ";", ",", ":", ">", "+", "~"] {
+            out = out
+                .replace(&format!(" {}", punctuation), punctuation)
+                .replace(&format!("{} ", punctuation), punctuation);
+        }
+        out.replace(";}", "}")
+    }
+
+    /// Asserts that removing the scope selectors from the scoped form of
+    /// `css` gives `css` back, so no rule or declaration moved.
+    fn assert_order_preserved(css: &str) {
+        let scoped = scope_css(css, "card1234").unwrap();
+        assert_eq!(
+            normalize(&scoped.replace(".card1234 ", "")),
+            normalize(css),
+            "scoped as:\n{}",
+            scoped
+        );
+    }
+
+    #[test]
+    fn preserves_the_order_of_representative_stylesheets() {
+        for css in [
+            ".a { color: red; color: blue; }\n.b { margin: 0; margin-left: 4px; }\n.a { color: green; }",
+            ".x, .y > .z { padding: 1px; padding: 2px }\n@media (min-width: 1px) {\n  .x { width: 3px }\n  .y { width: 4px }\n}\n.x { width: 5px }",
+            "@keyframes k { from { opacity: 0 } to { opacity: 1 } }\n.s { animation: k 1s; }\n@font-face { font-family: f; src: url(a.woff) }",
+            "@supports (display: grid) { @media print { .p { display: grid; grid: a / b } } }\n.q { --v: { x }; content: \"}\" }",
+            ".n { color: red; &:hover { color: blue } .m { color: green } }\n.after { z-index: 1 }",
+            "a[href$=\".pdf\"]::after { content: \" (pdf)\" }\nul li + li ~ p { color: red !important; color: blue }",
+            "@import url(x.css);\n.l { color: red }\n@container card (min-width: 3em) { .c { color: blue } }",
+        ] {
+            assert_order_preserved(css);
+        }
+    }
+
+    #[test]
+    fn preserves_the_order_of_generated_stylesheets() {
+        const SELECTORS: &[&str] = &[".a", "p", ".b > .c", "li + li", ".d:hover", "a::before"];
+        const DECLARATIONS: &[&str] = &[
+            "color: red",
+            "color: blue",
+            "margin: 0 1px",
+            "margin-left: 2px",
+            "width: calc(100% - 2px)",
+            "z-index: 3",
+            "background: url(a.png)",
+        ];
+        // A fixed linear congruential generator, so failures reproduce.
+        let mut state = 0x2545_f491_u32;
+        let mut next = |bound: usize| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as usize % bound
+        };
+        for _ in 0..200 {
+            let mut css = String::new();
+            for _ in 0..1 + next(6) {
+                let rule = |next: &mut dyn FnMut(usize) -> usize| {
+                    let selectors = (0..1 + next(2))
+                        .map(|_| SELECTORS[next(SELECTORS.len())])
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let declarations = (0..1 + next(4))
+                        .map(|_| DECLARATIONS[next(DECLARATIONS.len())])
+                        .collect::<Vec<_>>()
+                        .join("; ");
+                    format!("{} {{ {}; }}\n", selectors, declarations)
+                };
+                if next(4) == 0 {
+                    let inner = rule(&mut next);
+                    css.push_str(&format!(
+                        "@media (min-width: {}px) {{\n{}}}\n",
+                        next(900),
+                        inner
+                    ));
+                } else {
+                    css.push_str(&rule(&mut next));
+                }
+            }
+            assert_order_preserved(&css);
+        }
+    }
+
+    #[test]
+    fn runs_the_files_in_order_and_reports_the_one_in_error() {
+        let pipeline = Pipeline::new(Some(Scope::Class("card".to_string())));
+        let css = pipeline
+            .run(&[".a { color: red; }", ".b { color: blue; }"])
+            .unwrap();
+        assert_eq!(
+            normalize(&css),
+            normalize(".card .a { color: red; }\n.card .b { color: blue; }")
+        );
+        let (index, error) = pipeline.run(&[".a {}", ".b { color: red;"]).unwrap_err();
+        assert_eq!(index, 1);
+        assert_eq!(error.message, "unclosed `{`");
+    }
+
+    #[test]
+    fn runs_the_passes_options_ask_for() {
+        let css = ".a { margin-left: 0; } /* note */\n.a { margin-left: 0; }";
+        let mut pipeline = Pipeline::new(None);
+        assert_eq!(
+            normalize(&pipeline.run(&[css]).unwrap()),
+            normalize(&css.replace("/* note */", ""))
+        );
+        pipeline.keep_comments = true;
+        pipeline.dedupe = true;
+        pipeline.dir = Direction::Rtl;
+        let css = pipeline.run(&[css]).unwrap();
+        assert_eq!(css.matches("margin-right").count(), 1, "{}", css);
+        assert!(!css.contains("margin-left"), "{}", css);
+        #[cfg(not(feature = "minify"))]
+        assert!(css.contains("/* note */"), "{}", css);
+    }
+}
@@ -0,0 +1,445 @@
+//! Just enough understanding of `view!` input to find its root elements.
+
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
+use quote::quote;
+use syn::{Block, Expr, Ident, Lit, Macro, Stmt};
+
+/// Elements that never have a closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// A top-level node of a `view!` invocation.
+enum Root {
+    /// An element, with the index of the token just past its tag name, the
+    /// index of the value of its `class` attribute, if it has one, and whether
+    /// it has a `style` attribute.
+    Element {
+        name_end: usize,
+        class_value: Option<usize>,
+        has_style: bool,
+    },
+    /// Text, which needs no scope.
+    Text,
+    /// A `{...}` block or a component, which cannot carry the scope.
+    Other,
+}
+
+/// Returns the `view!` invocations whose value `block`, a function body, can
+/// return: its trailing expression, the branches of a trailing `if` or
+/// `match`, and the values of `return` expressions, outside of closures.
+pub(crate) fn result_views(block: &mut Block) -> Vec<&mut Macro> {
+    let mut views = Vec::new();
+    block_views(block, true, &mut views);
+    views
+}
+
+/// Collects the views `block` can return, and, if `tail`, the views it
+/// evaluates to.
+fn block_views<'a>(block: &'a mut Block, tail: bool, views: &mut Vec<&'a mut Macro>) {
+    let count = block.stmts.len();
+    for (i, stmt) in block.stmts.iter_mut().enumerate() {
+        let value = tail && i + 1 == count;
+        match stmt {
+            Stmt::Local(local) => {
+                if let Some(init) = &mut local.init {
+                    expr_views(&mut init.expr, false, views);
+                    if let Some((_, diverge)) = &mut init.diverge {
+                        expr_views(diverge, false, views);
+                    }
+                }
+            }
+            Stmt::Expr(expr, semi) => expr_views(expr, value && semi.is_none(), views),
+            Stmt::Macro(stmt) => {
+                if value && stmt.semi_token.is_none() && is_view(&stmt.mac) {
+                    views.push(&mut stmt.mac);
+                }
+            }
+            Stmt::Item(_) => {}
+        }
+    }
+}
+
+/// Collects the views `expr` can return, and, if `tail`, the views it
+/// evaluates to.
+fn expr_views<'a>(expr: &'a mut Expr, tail: bool, views: &mut Vec<&'a mut Macro>) {
+    match expr {
+        Expr::Macro(expr) if tail && is_view(&expr.mac) => views.push(&mut expr.mac),
+        Expr::Return(expr) => {
+            if let Some(value) = &mut expr.expr {
+                expr_views(value, true, views);
+            }
+        }
+        Expr::If(expr) => {
+            expr_views(&mut expr.cond, false, views);
+            block_views(&mut expr.then_branch, tail, views);
+            if let Some((_, else_branch)) = &mut expr.else_branch {
+                expr_views(else_branch, tail, views);
+            }
+        }
+        Expr::Match(expr) => {
+            expr_views(&mut expr.expr, false, views);
+            for arm in &mut expr.arms {
+                expr_views(&mut arm.body, tail, views);
+            }
+        }
+        Expr::Block(expr) => block_views(&mut expr.block, tail, views),
+        Expr::Unsafe(expr) => block_views(&mut expr.block, tail, views),
+        Expr::Paren(expr) => expr_views(&mut expr.expr, tail, views),
+        // Branches of different element types meet in `.into_view()`.
+        Expr::MethodCall(expr) if expr.method == "into_view" || expr.method == "into_any" => {
+            expr_views(&mut expr.receiver, tail, views)
+        }
+        Expr::Loop(expr) => block_views(&mut expr.body, false, views),
+        Expr::While(expr) => block_views(&mut expr.body, false, views),
+        Expr::ForLoop(expr) => block_views(&mut expr.body, false, views),
+        // A `return` in a closure or an `async` block returns from that.
+        _ => {}
+    }
+}
+
+fn is_view(mac: &Macro) -> bool {
+    mac.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "view")
+}
+
+/// The classes [`scope_root`] adds to a root element.
+pub(crate) enum RootClass<'a> {
+    /// The scope class alone, known at compile time.
+    Scope(&'a str),
+    /// A `String` variable holding the scope class and the classes passed in
+    /// by the parent, known at runtime.
+    Variable(&'a Ident),
+}
+
+/// Adds the `scope` classes to the root elements of a `view!` invocation.
+///
+/// A string literal `class` already on a root is merged with the scope
+/// (`class="large"` becomes `class="card1234 large"`); any other `class` value
+/// is left alone and the scope class is added through leptos'
+/// `class=(name, true)` form instead, which a runtime list of classes cannot
+/// use.
+///
+/// A non-empty `style` value, in `view!` syntax, is set as the `style`
+/// attribute of the roots as well, along with the `attributes` given.
+///
+/// Several sibling elements each get the scope, as do the elements of a root
+/// fragment (`<>...</>`), while text between them needs none. Components and `{...}` blocks at the root render markup the scope
+/// cannot reach, so they are an error.
+pub(crate) fn scope_root(
+    mac: &mut Macro,
+    scope: &RootClass<'_>,
+    style: TokenStream,
+    attributes: TokenStream,
+) -> Result<(), String> {
+    let mut tokens: Vec<TokenTree> = mac.tokens.clone().into_iter().collect();
+    let roots = roots(&tokens)?;
+    let elements: Vec<(usize, Option<usize>, bool)> = roots
+        .iter()
+        .filter_map(|root| match root {
+            Root::Element {
+                name_end,
+                class_value,
+                has_style,
+            } => Some((*name_end, *class_value, *has_style)),
+            Root::Text | Root::Other => None,
+        })
+        .collect();
+    if roots.iter().any(|root| matches!(root, Root::Other)) {
+        return Err(match roots.as_slice() {
+            [_] => "the root of the `view!` is not an HTML element".to_string(),
+            _ => "a root node of the `view!` is a component or a `{...}` block, which cannot carry the scope; wrap the roots in a single element".to_string(),
+        });
+    }
+    if elements.is_empty() {
+        return Err("the `view!` has no root element".to_string());
+    }
+    if elements.len() > 1 && matches!(scope, RootClass::Variable(_)) {
+        return Err(format!(
+            "the `view!` has {} root elements, so it is unclear which one the `class` prop belongs to; wrap them in a single element",
+            elements.len()
+        ));
+    }
+    if elements.iter().any(|&(_, _, has_style)| has_style) && !style.is_empty() {
+        return Err(
+            "the root element already has a `style`, which would be replaced by `vars`".to_string(),
+        );
+    }
+
+    // From the last root to the first, so the indices of earlier ones stay
+    // valid.
+    for &(name_end, class_value, _) in elements.iter().rev() {
+        let style = style
+            .clone()
+            .into_iter()
+            .chain(attributes.clone())
+            .collect();
+        scope_element(&mut tokens, name_end, class_value, scope, style)?;
+    }
+    mac.tokens = tokens.into_iter().collect::<TokenStream>();
+    Ok(())
+}
+
+/// Returns the `view!` attribute setting `name` to `value`, a runtime value,
+/// as written. On the server, leptos starts a runtime `class` with a space and
+/// ends a runtime `style` with a `;`, which a spread attribute is spared.
+pub(crate) fn spread_attribute(name: &str, value: TokenStream) -> TokenStream {
+    quote!({..[(#name, ::leptos::IntoAttribute::into_attribute(#value))]})
+}
+
+/// Adds the scope classes and the attributes of `style` to the element whose
+/// tag name ends at `name_end`.
+fn scope_element(
+    tokens: &mut Vec<TokenTree>,
+    name_end: usize,
+    class_value: Option<usize>,
+    scope: &RootClass<'_>,
+    style: TokenStream,
+) -> Result<(), String> {
+    let existing = class_value.and_then(|index| match &tokens[index] {
+        TokenTree::Literal(literal) => match Lit::new(literal.clone()) {
+            Lit::Str(existing) => Some((index, existing.value())),
+            _ => None,
+        },
+        _ => None,
+    });
+
+    match (class_value, existing, scope) {
+        (_, Some((index, existing)), RootClass::Scope(scope)) => {
+            let literal = proc_macro2::Literal::string(&format!("{} {}", scope, existing));
+            tokens[index] = TokenTree::Literal(literal);
+        }
+        (_, Some((index, existing)), RootClass::Variable(classes)) => {
+            let merged = spread_attribute("class", quote!(format!("{} {}", #classes, #existing)));
+            tokens.splice(index - 2..index + 1, merged);
+        }
+        (Some(_), None, RootClass::Scope(scope)) => {
+            let attr = quote!(class=(#scope, true));
+            tokens.splice(name_end..name_end, attr);
+        }
+        (Some(_), None, RootClass::Variable(_)) => {
+            return Err(
+                "the root element's `class` is not a string literal, so it cannot be merged with the `class` prop"
+                    .to_string(),
+            );
+        }
+        (None, None, RootClass::Scope(scope)) => {
+            let attr = quote!(class=#scope);
+            tokens.splice(name_end..name_end, attr);
+        }
+        (None, None, RootClass::Variable(classes)) => {
+            let attr = spread_attribute("class", quote!(#classes));
+            tokens.splice(name_end..name_end, attr);
+        }
+    }
+    // Indices past `name_end` are stale from here on.
+    tokens.splice(name_end..name_end, style);
+    Ok(())
+}
+
+/// Adds `attribute` to every HTML element of every `view!` invocation found in
+/// `tokens`, including invocations nested in blocks and closures.
+pub(crate) fn mark_views(tokens: TokenStream, attribute: &TokenStream) -> TokenStream {
+    let mut out: Vec<TokenTree> = Vec::new();
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                let after_view = matches!(out.as_slice(), [.., TokenTree::Ident(ident), bang]
+                    if ident == "view" && is_punct(bang, '!'));
+                let inner = if after_view {
+                    mark_elements(group.stream(), attribute)
+                } else {
+                    group.stream()
+                };
+                let mut marked = Group::new(group.delimiter(), mark_views(inner, attribute));
+                marked.set_span(group.span());
+                out.push(TokenTree::Group(marked));
+            }
+            token => out.push(token),
+        }
+    }
+    out.into_iter().collect()
+}
+
+/// Adds `attribute` to the opening tag of every HTML element in `view!` input.
+fn mark_elements(tokens: TokenStream, attribute: &TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        // `Vec<u8>` in an unbraced attribute value is not a tag.
+        let starts_tag = is_punct(&tokens[i], '<')
+            && !matches!(out.last(), Some(TokenTree::Ident(_)))
+            && matches!(tokens.get(i + 1), Some(TokenTree::Ident(_)));
+        if starts_tag {
+            if let Ok(tag) = open_tag(&tokens, i) {
+                out.extend_from_slice(&tokens[i..tag.name_end]);
+                if !tag.component {
+                    out.extend(attribute.clone());
+                }
+                i = tag.name_end;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out.into_iter().collect()
+}
+
+/// Lists the top-level nodes of `view!` input.
+fn roots(tokens: &[TokenTree]) -> Result<Vec<Root>, String> {
+    let mut roots = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        // The children of a fragment, `<>...</>`, are roots just like its
+        // siblings.
+        if is_punct(&tokens[i], '<') {
+            match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(next), _) if is_punct(next, '>') => {
+                    i += 2;
+                    continue;
+                }
+                (Some(slash), Some(next)) if is_punct(slash, '/') && is_punct(next, '>') => {
+                    i += 3;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if is_punct(&tokens[i], '<') {
+            let tag = open_tag(tokens, i)?;
+            let mut end = tag.end;
+            if !tag.self_closing {
+                end = element_end(tokens, end)?;
+            }
+            roots.push(if tag.component {
+                Root::Other
+            } else {
+                Root::Element {
+                    name_end: tag.name_end,
+                    class_value: tag.class_value,
+                    has_style: tag.has_style,
+                }
+            });
+            i = end;
+            continue;
+        }
+        match &tokens[i] {
+            TokenTree::Literal(_) => roots.push(Root::Text),
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                roots.push(Root::Other)
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(roots)
+}
+
+/// An opening tag found by [`open_tag`].
+struct OpenTag {
+    name_end: usize,
+    class_value: Option<usize>,
+    has_style: bool,
+    /// Index just past the closing `>`.
+    end: usize,
+    self_closing: bool,
+    /// Whether the tag is a component (`<Card/>`) rather than an element.
+    component: bool,
+}
+
+/// Reads the opening tag starting with the `<` at `start`.
+fn open_tag(tokens: &[TokenTree], start: usize) -> Result<OpenTag, String> {
+    let mut i = start + 1;
+    let mut name = String::new();
+    // Tag names may contain dashes (`my-card`) or colons (`svg:path`).
+    while let Some(token) = tokens.get(i) {
+        let expects_ident = name.is_empty() || name.ends_with(['-', ':']);
+        match token {
+            TokenTree::Ident(ident) if expects_ident => name.push_str(&ident.to_string()),
+            TokenTree::Punct(punct) if !expects_ident && matches!(punct.as_char(), '-' | ':') => {
+                name.push(punct.as_char())
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    if name.is_empty() {
+        return Err("expected an element name after `<`".to_string());
+    }
+    let name_end = i;
+
+    let mut class_value = None;
+    let mut has_style = false;
+    while let Some(token) = tokens.get(i) {
+        if is_punct(token, '>') {
+            let self_closing = is_punct(&tokens[i - 1], '/')
+                || VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str());
+            return Ok(OpenTag {
+                name_end,
+                class_value,
+                has_style,
+                end: i + 1,
+                self_closing,
+                component: name.starts_with(|c: char| c.is_ascii_uppercase()),
+            });
+        }
+        // A bare `class=`, as opposed to `class:name=` or `data-class=`.
+        let bare_attribute = |name: &str| {
+            matches!(token, TokenTree::Ident(ident) if ident == name)
+                && !is_punct(&tokens[i - 1], ':')
+                && !is_punct(&tokens[i - 1], '-')
+                && tokens.get(i + 1).is_some_and(|next| is_punct(next, '='))
+        };
+        if bare_attribute("class") {
+            class_value = Some(i + 2);
+        }
+        if bare_attribute("style") {
+            has_style = true;
+        }
+        i += 1;
+    }
+    Err(format!("unclosed `<{}>` tag", name))
+}
+
+/// Returns the index just past the closing tag of the element whose children
+/// start at `start`.
+fn element_end(tokens: &[TokenTree], start: usize) -> Result<usize, String> {
+    let mut depth = 1usize;
+    let mut i = start;
+    while i < tokens.len() {
+        if is_punct(&tokens[i], '<') {
+            if tokens.get(i + 1).is_some_and(|next| is_punct(next, '/')) {
+                while i < tokens.len() && !is_punct(&tokens[i], '>') {
+                    i += 1;
+                }
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i + 1);
+                }
+            } else if tokens.get(i + 1).is_some_and(|next| is_punct(next, '>')) {
+                // A fragment, closed by `</>`.
+                depth += 1;
+                i += 2;
+                continue;
+            } else {
+                let tag = open_tag(tokens, i)?;
+                if !tag.self_closing {
+                    depth += 1;
+                }
+                i = tag.end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    Err("unclosed element in `view!`".to_string())
+}
+
+fn is_punct(token: &TokenTree, c: char) -> bool {
+    matches!(token, TokenTree::Punct(punct) if punct.as_char() == c)
+}
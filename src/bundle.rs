@@ -0,0 +1,22 @@
+//! Linking the stylesheet written by the `bundle` feature.
+
+use leptos::{view, IntoView};
+
+/// Renders a `<link rel="stylesheet">` to the CSS bundle, which the app serves
+/// at `href`.
+///
+/// With the `bundle` feature the components render no `<style>` of their own,
+/// so this must be rendered once, typically in the document `<head>`:
+///
+/// ```ignore
+/// use leptos_styles::link_bundle;
+///
+/// view! {
+///     <head>{link_bundle("/pkg/styles.css")}</head>
+///     <body><App/></body>
+/// }
+/// ```
+pub fn link_bundle(href: impl Into<String>) -> impl IntoView {
+    let href = href.into();
+    view! { <link rel="stylesheet" href=href/> }
+}
@@ -0,0 +1,168 @@
+//! Keeping track of the stylesheets rendered inline on the server, and of
+//! those added to the document head in the browser.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+type Rendered = RefCell<HashSet<&'static str>>;
+
+thread_local! {
+    /// The scopes rendered by each live runtime, by the hash of its ID.
+    static RENDERED: RefCell<HashMap<u64, Weak<Rendered>>> = RefCell::new(HashMap::new());
+}
+
+/// Keeps the record of the stylesheets a runtime rendered alive.
+///
+/// Every rendered instance holds one until it is cleaned up, so the record is
+/// dropped along with the runtime, or once no instance remains.
+#[doc(hidden)]
+pub struct InlineStyles {
+    _rendered: Rc<Rendered>,
+}
+
+/// Returns whether the stylesheet of the scope `id` is new to `runtime`, and
+/// records it as rendered. Components sharing a scope share the record, so
+/// the first of them to render carries the stylesheet for all of them.
+#[doc(hidden)]
+pub fn inline_style(runtime: impl Hash, id: &'static str) -> (bool, InlineStyles) {
+    let mut hasher = DefaultHasher::new();
+    runtime.hash(&mut hasher);
+    let key = hasher.finish();
+    let rendered = RENDERED.with(|runtimes| {
+        let mut runtimes = runtimes.borrow_mut();
+        // The records of disposed runtimes are gone by now.
+        runtimes.retain(|_, rendered| rendered.strong_count() > 0);
+        match runtimes.get(&key).and_then(Weak::upgrade) {
+            Some(rendered) => rendered,
+            None => {
+                let rendered = Rc::new(RefCell::new(HashSet::new()));
+                runtimes.insert(key, Rc::downgrade(&rendered));
+                rendered
+            }
+        }
+    });
+    let first = rendered.borrow_mut().insert(id);
+    (
+        first,
+        InlineStyles {
+            _rendered: rendered,
+        },
+    )
+}
+
+/// Returns the `<style>` of a scope in the document head, given the one
+/// already there, if any. Otherwise the one the server rendered elsewhere in
+/// the page, or else a new one from `create`, is handed to `append_to_head`,
+/// and returned if it was added.
+///
+/// The browser half of the injection, kept apart from the DOM so that it can
+/// be tested without one.
+#[doc(hidden)]
+pub fn head_style<S>(
+    in_head: Option<S>,
+    rendered: impl FnOnce() -> Option<S>,
+    create: impl FnOnce() -> Option<S>,
+    append_to_head: impl FnOnce(&S) -> bool,
+) -> Option<S> {
+    match in_head {
+        Some(style) => Some(style),
+        None => rendered()
+            .or_else(create)
+            .filter(|style| append_to_head(style)),
+    }
+}
+
+/// Returns the `data-style-mounts` count of a `<style>` once one more
+/// instance is mounted, given the current one.
+#[doc(hidden)]
+pub fn mounted(mounts: Option<&str>) -> String {
+    (mount_count(mounts) + 1).to_string()
+}
+
+/// Returns the `data-style-mounts` count of a `<style>` once one of its
+/// instances is cleaned up, or `None` when it was the last one and the
+/// `<style>` goes.
+#[doc(hidden)]
+pub fn unmounted(mounts: Option<&str>) -> Option<String> {
+    match mount_count(mounts) {
+        0 | 1 => None,
+        n => Some((n - 1).to_string()),
+    }
+}
+
+fn mount_count(mounts: Option<&str>) -> usize {
+    mounts.and_then(|mounts| mounts.parse().ok()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn a_stylesheet_in_the_head_is_kept() {
+        let appended = Cell::new(false);
+        let style = head_style(
+            Some("head"),
+            || panic!("looked for another stylesheet"),
+            || panic!("created a stylesheet"),
+            |_| {
+                appended.set(true);
+                true
+            },
+        );
+        assert_eq!(style, Some("head"));
+        assert!(!appended.get());
+    }
+
+    #[test]
+    fn a_rendered_stylesheet_is_moved_to_the_head() {
+        let head = RefCell::new(Vec::<String>::new());
+        let append = |style: &&str| {
+            head.borrow_mut().push(style.to_string());
+            true
+        };
+        let style = head_style(None, || Some("inline"), || panic!("created"), append);
+        assert_eq!(style, Some("inline"));
+        let style = head_style(None, || None, || Some("new"), append);
+        assert_eq!(style, Some("new"));
+        assert_eq!(*head.borrow(), ["inline", "new"]);
+        assert_eq!(head_style(None, || None, || Some("new"), |_| false), None);
+    }
+
+    #[test]
+    fn the_last_instance_to_go_removes_the_stylesheet() {
+        assert_eq!(mounted(None), "1");
+        assert_eq!(mounted(Some("1")), "2");
+        assert_eq!(unmounted(Some("2")).as_deref(), Some("1"));
+        assert_eq!(unmounted(Some("1")), None);
+        assert_eq!(unmounted(Some("oops")), None);
+    }
+
+    #[test]
+    fn a_stylesheet_is_rendered_once_per_runtime() {
+        let (first, card) = inline_style(1, "card");
+        assert!(first);
+        let (again, _again) = inline_style(1, "card");
+        assert!(!again);
+        let (other, _other) = inline_style(1, "badge");
+        assert!(other);
+        let (elsewhere, _elsewhere) = inline_style(2, "card");
+        assert!(elsewhere);
+        drop(card);
+    }
+
+    #[test]
+    fn records_are_dropped_with_their_instances() {
+        let (_, card) = inline_style(3, "card");
+        drop(card);
+        let (first, card) = inline_style(3, "card");
+        assert!(first);
+        drop(card);
+        let (_, _badge) = inline_style(4, "badge");
+        assert_eq!(RENDERED.with(|runtimes| runtimes.borrow().len()), 1);
+    }
+}
@@ -0,0 +1,20 @@
+//! Rendering component styles in the document head with `leptos_meta`.
+
+use leptos::{use_context, view, DynAttrs, IntoView};
+use leptos_meta::{MetaContext, Style};
+
+/// Adds the CSS of the scope `id` to the document head with `leptos_meta`'s
+/// `<Style>`, and returns whether it did, which requires a [`MetaContext`] to
+/// be provided.
+///
+/// Components call this while rendering on the server. The `<style>` carries
+/// the same `data-style-id` attribute as an inline one, so that hydrating
+/// components find it and do not inject their stylesheet again.
+#[doc(hidden)]
+pub fn meta_style(id: &'static str, css: &'static str) -> bool {
+    if use_context::<MetaContext>().is_none() {
+        return false;
+    }
+    let _ = view! { <Style id=id attr:data-style-id=id>{css}</Style> }.into_view();
+    true
+}
@@ -0,0 +1,153 @@
+//! Collecting component styles for the document head.
+
+use leptos::{provide_context, use_context};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Collects the scoped CSS of the `#[styles]` components rendered beneath it.
+///
+/// When a registry is available through context, components register their
+/// stylesheet with it instead of rendering an inline `<style>`, so that a
+/// server-rendered page can ship all of its component CSS as one stylesheet in
+/// the `<head>`:
+///
+/// ```ignore
+/// use leptos::*;
+/// use leptos_styles::{collect_styles, provide_style_registry};
+///
+/// // Wherever the document is rendered on the server:
+/// let registry = provide_style_registry();
+/// let body = view! { <App/> }.into_view().render_to_string();
+/// // Every component in `body` has now registered its CSS.
+/// let head = registry.html();
+/// ```
+///
+/// Each scope is only recorded once, however many instances of the component
+/// were rendered.
+///
+/// The `<style>` elements written by [`StyleRegistry::html`] are tagged with
+/// their scope, so that components hydrating in the browser find them and do
+/// not inject their stylesheet a second time.
+#[derive(Clone, Default)]
+pub struct StyleRegistry {
+    styles: Rc<RefCell<Vec<(String, String)>>>,
+}
+
+impl StyleRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the CSS of the scope `id`, unless that scope is already known.
+    pub fn register(&self, id: &str, css: &str) {
+        let mut styles = self.styles.borrow_mut();
+        if !styles.iter().any(|(existing, _)| existing == id) {
+            styles.push((id.to_string(), css.to_string()));
+        }
+    }
+
+    /// Returns the CSS of every registered scope, in registration order.
+    pub fn css(&self) -> String {
+        self.styles
+            .borrow()
+            .iter()
+            .map(|(_, css)| css.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns a `<style>` element per registered scope, tagged with a
+    /// `data-style-id` attribute naming it, for the document head. Any `</` of
+    /// the CSS is written `<\/`, so that the text of a stylesheet cannot close
+    /// its element.
+    pub fn html(&self) -> String {
+        self.styles
+            .borrow()
+            .iter()
+            .map(|(id, css)| {
+                format!(
+                    "<style data-style-id=\"{}\">{}</style>",
+                    id,
+                    style_text(css)
+                )
+            })
+            .collect()
+    }
+
+    /// Like [`StyleRegistry::html`], with `nonce` as the `nonce` attribute of
+    /// every `<style>`, for pages served with a Content Security Policy. The
+    /// nonce is escaped as an attribute value.
+    pub fn html_with_nonce(&self, nonce: &str) -> String {
+        self.styles
+            .borrow()
+            .iter()
+            .map(|(id, css)| {
+                format!(
+                    "<style data-style-id=\"{}\" nonce=\"{}\">{}</style>",
+                    id,
+                    attribute_value(nonce),
+                    style_text(css)
+                )
+            })
+            .collect()
+    }
+}
+
+/// Escapes `css` for the content of a `<style>`, where `</style>` would end
+/// the element. `\/` is a `/` to CSS, in strings and selectors alike.
+fn style_text(css: &str) -> String {
+    css.replace("</", "<\\/")
+}
+
+/// Escapes `value` for a double-quoted attribute.
+fn attribute_value(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Provides a new [`StyleRegistry`] to the current component and its children.
+pub fn provide_style_registry() -> StyleRegistry {
+    let registry = StyleRegistry::new();
+    provide_context(registry.clone());
+    registry
+}
+
+/// Returns the CSS gathered by the [`StyleRegistry`] in context, or an empty
+/// string when none was provided.
+pub fn collect_styles() -> String {
+    use_context::<StyleRegistry>()
+        .map(|registry| registry.css())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stylesheets_cannot_close_their_element() {
+        let registry = StyleRegistry::new();
+        registry.register("card1", ".card1 { content: \"</style><script>\"; }");
+        assert_eq!(
+            registry.html(),
+            "<style data-style-id=\"card1\">.card1 { content: \"<\\/style><script>\"; }</style>"
+        );
+    }
+
+    #[test]
+    fn the_nonce_is_an_attribute_of_every_stylesheet() {
+        let registry = StyleRegistry::new();
+        registry.register("card1", ".card1 {}");
+        registry.register("badge1", ".badge1 {}");
+        assert_eq!(
+            registry.html_with_nonce("abc"),
+            "<style data-style-id=\"card1\" nonce=\"abc\">.card1 {}</style>\
+             <style data-style-id=\"badge1\" nonce=\"abc\">.badge1 {}</style>"
+        );
+        assert_eq!(
+            registry.html_with_nonce("a\"b&c"),
+            "<style data-style-id=\"card1\" nonce=\"a&quot;b&amp;c\">.card1 {}</style>\
+             <style data-style-id=\"badge1\" nonce=\"a&quot;b&amp;c\">.badge1 {}</style>"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Components compiled with the `bundle` feature, checked against the bundle.
+
+#![cfg(feature = "bundle")]
+
+use leptos::*;
+use leptos_styles::styles;
+use std::path::PathBuf;
+
+#[component]
+#[styles("card.css")]
+fn BundledCard() -> impl IntoView {
+    view! { <article class="card">"..."</article> }
+}
+
+/// The bundle written while this crate, the root of its workspace, was
+/// compiled.
+fn bundle() -> String {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let path = match option_env!("LEPTOS_STYLES_BUNDLE") {
+        Some(path) => root.join(path),
+        None => option_env!("CARGO_TARGET_DIR")
+            .map_or_else(|| root.join("target"), |target| root.join(target))
+            .join("leptos_styles")
+            .join("bundle.css"),
+    };
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn bundled_components_render_no_stylesheet() {
+    let runtime = create_runtime();
+    let html = view! { <BundledCard/> }
+        .into_view()
+        .render_to_string()
+        .to_string();
+    runtime.dispose();
+    assert!(!html.contains("<style"), "{}", html);
+    assert!(
+        html.contains(&format!("class=\"{}\"", BUNDLED_CARD_SCOPE)),
+        "{}",
+        html
+    );
+
+    // With `minify`, the bundle has neither spaces nor last semicolons.
+    let compact = |css: &str| css.replace([' ', ';'], "");
+    let bundle = compact(&bundle());
+    let scope = BUNDLED_CARD_SCOPE;
+    for rule in [
+        format!(".{} .card {{ color: red; }}", scope),
+        format!(".{} .card:hover .title {{ color: blue; }}", scope),
+    ] {
+        assert!(bundle.contains(&compact(&rule)), "{}", bundle);
+    }
+}
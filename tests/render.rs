@@ -0,0 +1,434 @@
+//! Components rendered on the server, checked against their scoped CSS.
+
+// With `bundle`, components render no `<style>` to inspect.
+#![cfg(not(feature = "bundle"))]
+
+use leptos::*;
+use leptos_styles::styles;
+
+/// Renders `view` to HTML in a runtime of its own.
+fn render<V: IntoView>(view: impl FnOnce() -> V) -> String {
+    let runtime = create_runtime();
+    let html = view().into_view().render_to_string().to_string();
+    runtime.dispose();
+    html
+}
+
+/// Returns the contents of the `<style>` elements of `html`, in order.
+fn styles_of(html: &str) -> Vec<&str> {
+    html.split("<style")
+        .skip(1)
+        .map(|style| {
+            let css = &style[style.find('>').unwrap() + 1..];
+            &css[..css.find("</style>").unwrap()]
+        })
+        .collect()
+}
+
+/// Returns the value of the first `attribute` following `from` in `html`.
+fn attribute<'a>(html: &'a str, from: &str, attribute: &str) -> &'a str {
+    let rest = &html[html.find(from).unwrap()..];
+    let pattern = format!(" {}=\"", attribute);
+    let value = &rest[rest.find(&pattern).unwrap() + pattern.len()..];
+    &value[..value.find('"').unwrap()]
+}
+
+#[component]
+#[styles("card.css", wrapper = false)]
+fn Card() -> impl IntoView {
+    view! { <article class="card"><h2 class="title">"Title"</h2></article> }
+}
+
+#[test]
+fn rules_without_a_wrapper_match_the_root() {
+    let html = render(|| view! { <Card/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(
+        attribute(&html, "<article", "class"),
+        format!("{} card", id)
+    );
+    let css = styles_of(&html).concat();
+    assert!(css.contains(&format!(".card.{}", id)), "{}", css);
+    assert!(
+        css.contains(&format!(".card:hover.{} .title", id)),
+        "{}",
+        css
+    );
+    assert!(!html.contains("<div"), "{}", html);
+}
+
+#[component]
+#[styles("card.css", wrapper = false)]
+fn CardRow() -> impl IntoView {
+    view! {
+        <section class="card">"First"</section>
+        <aside class="card">"Second"</aside>
+    }
+}
+
+#[test]
+fn rules_without_a_wrapper_match_every_root() {
+    let html = render(|| view! { <CardRow/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    for root in ["<section", "<aside"] {
+        assert_eq!(attribute(&html, root, "class"), format!("{} card", id));
+    }
+    let css = styles_of(&html).concat();
+    assert!(css.contains(&format!(".card.{}", id)), "{}", css);
+}
+
+#[styles("card.css", wrapper = false, class_prop)]
+#[component]
+fn ClassCard() -> impl IntoView {
+    view! { <article class="card">"..."</article> }
+}
+
+#[test]
+fn class_prop_without_a_wrapper_styles_the_root() {
+    let html = render(|| view! { <ClassCard class="large".to_string()/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(
+        attribute(&html, "<article", "class"),
+        format!("{} large card", id)
+    );
+    let css = styles_of(&html).concat();
+    assert!(css.contains(&format!(".card.{}", id)), "{}", css);
+
+    let html = render(|| view! { <ClassCard/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(
+        attribute(&html, "<article", "class"),
+        format!("{} card", id)
+    );
+}
+
+#[styles("card.css", class_prop)]
+#[component]
+fn WrappedClassCard() -> impl IntoView {
+    view! { <article class="card">"..."</article> }
+}
+
+#[test]
+fn class_prop_joins_the_wrapper_class() {
+    let html = render(|| view! { <WrappedClassCard class="large".to_string()/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<div", "class"), format!("{} large", id));
+
+    let html = render(|| view! { <WrappedClassCard class="  ".to_string()/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<div", "class"), id);
+
+    let html = render(|| view! { <WrappedClassCard/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<div", "class"), id);
+}
+
+#[test]
+fn each_runtime_renders_a_stylesheet_once() {
+    let html = render(|| view! { <Card/><Card/><Card/> });
+    assert_eq!(styles_of(&html).len(), 1, "{}", html);
+    // A new runtime, as for the next request, renders its own.
+    let html = render(|| view! { <Card/> });
+    assert_eq!(styles_of(&html).len(), 1, "{}", html);
+}
+
+#[component]
+#[styles("card.css", scope = "card-family")]
+fn FamilyHeader() -> impl IntoView {
+    view! { <h2 class="title">"Title"</h2> }
+}
+
+#[component]
+#[styles("card.css", scope = "card-family")]
+fn FamilyBody() -> impl IntoView {
+    view! { <p class="card">"Body"</p> }
+}
+
+#[component]
+#[styles("card.css", scope = "card-family")]
+fn FamilyFooter() -> impl IntoView {
+    view! { <footer class="card">"Footer"</footer> }
+}
+
+#[component]
+#[styles("card.css")]
+fn PlainCard() -> impl IntoView {
+    view! { <p class="card">"..."</p> }
+}
+
+#[component]
+#[styles("card.css")]
+fn OtherPlainCard() -> impl IntoView {
+    view! { <p class="card">"..."</p> }
+}
+
+#[component]
+#[styles("card.css", dir = "rtl")]
+fn RtlCard() -> impl IntoView {
+    view! { <p class="card">"..."</p> }
+}
+
+#[test]
+fn components_sharing_a_scope_render_its_stylesheet_once() {
+    let html = render(|| view! { <FamilyHeader/><FamilyBody/><FamilyFooter/> });
+    assert_eq!(styles_of(&html).len(), 1, "{}", html);
+    assert_eq!(attribute(&html, "<style", "data-style-id"), "card-family");
+
+    let html = render(|| view! { <PlainCard/><OtherPlainCard/><PlainCard/> });
+    assert_eq!(styles_of(&html).len(), 1, "{}", html);
+
+    // Mirrored, the same file is another stylesheet.
+    let html = render(|| view! { <PlainCard/><RtlCard/> });
+    assert_eq!(styles_of(&html).len(), 2, "{}", html);
+}
+
+#[test]
+fn scopes_every_rule_of_a_css_file() {
+    let html = render(|| view! { <PlainCard/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<div", "class"), id);
+    let css = styles_of(&html).concat();
+    assert!(css.contains(&format!(".{} .card", id)), "{}", css);
+    assert!(
+        css.contains(&format!(".{} .card:hover .title", id)),
+        "{}",
+        css
+    );
+}
+
+#[test]
+fn instances_share_a_class_rather_than_an_id() {
+    let html = render(|| (0..10).map(|_| view! { <PlainCard/> }).collect_view());
+    let id = attribute(&html, "<style", "data-style-id");
+    assert!(!html.contains(" id="), "{}", html);
+    let wrappers: Vec<&str> = html.split("<div").skip(1).collect();
+    assert_eq!(wrappers.len(), 10);
+    assert!(
+        wrappers.iter().all(|div| attribute(div, "", "class") == id),
+        "{}",
+        html
+    );
+}
+
+#[component]
+#[styles("base.css", "variants.css")]
+fn Button() -> impl IntoView {
+    view! { <button class="button primary">"Save"</button> }
+}
+
+#[test]
+fn files_are_scoped_together_in_order() {
+    let html = render(|| view! { <Button/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<div", "class"), id);
+    let css = styles_of(&html).concat();
+    let base = css.find(&format!(".{} .button", id));
+    let variants = css.find(&format!(".{} .button.primary", id));
+    assert!(base.is_some() && variants.is_some(), "{}", css);
+    assert!(base < variants, "{}", css);
+}
+
+#[component]
+#[styles("card.css", classes)]
+fn ListedCard() -> impl IntoView {
+    view! { <article class=listed_card_classes::CARD>"..."</article> }
+}
+
+#[test]
+fn classes_module_names_the_classes_as_written() {
+    assert_eq!(listed_card_classes::CARD, "card");
+    assert_eq!(listed_card_classes::TITLE, "title");
+    let html = render(|| view! { <ListedCard/> });
+    assert_eq!(attribute(&html, "<article", "class").trim(), "card");
+}
+
+#[cfg(feature = "leptos")]
+#[test]
+fn registered_stylesheets_are_tagged_for_hydration() {
+    let runtime = create_runtime();
+    let registry = leptos_styles::provide_style_registry();
+    let html = view! { <PlainCard/><PlainCard/> }
+        .into_view()
+        .render_to_string()
+        .to_string();
+    runtime.dispose();
+    assert!(!html.contains("<style"), "{}", html);
+    let id = attribute(&html, "<div", "class");
+    let head = registry.html();
+    assert_eq!(head.matches("<style").count(), 1, "{}", head);
+    assert!(
+        head.starts_with(&format!("<style data-style-id=\"{}\">", id)),
+        "{}",
+        head
+    );
+}
+
+#[cfg(feature = "meta")]
+#[test]
+fn without_a_meta_context_the_stylesheet_is_inlined() {
+    let html = render(|| view! { <PlainCard/> });
+    let id = attribute(&html, "<div", "class");
+    assert!(
+        html.contains(&format!("<style data-style-id=\"{}\"", id)),
+        "{}",
+        html
+    );
+
+    // With one, `leptos_meta` renders it in the head instead.
+    let html = render(|| {
+        leptos_meta::provide_meta_context();
+        view! { <PlainCard/> }
+    });
+    assert!(!html.contains("<style"), "{}", html);
+}
+
+#[component]
+#[styles("card.css")]
+fn Status(done: bool) -> impl IntoView {
+    if done {
+        return view! { <p class="card">"Done"</p> }.into_view();
+    }
+    view! { <p class="title">"Pending"</p> }.into_view()
+}
+
+#[test]
+fn early_returns_are_wrapped_too() {
+    for (done, text) in [(true, "Done"), (false, "Pending")] {
+        let html = render(|| view! { <Status done/> });
+        let id = attribute(&html, "<style", "data-style-id");
+        let wrapper = &html[html.find("<div").unwrap()..];
+        assert_eq!(attribute(wrapper, "<div", "class"), id);
+        assert!(wrapper.contains(text), "{}", html);
+    }
+}
+
+#[component]
+#[styles("card.css")]
+fn Progress(done: bool) -> impl IntoView {
+    if done {
+        view! { <p class="card">"Done"</p> }.into_view()
+    } else {
+        view! { <p class="title">"Pending"</p> }.into_view()
+    }
+}
+
+#[test]
+fn both_branches_of_a_conditional_body_are_wrapped() {
+    for (done, text) in [(true, "Done"), (false, "Pending")] {
+        let html = render(|| view! { <Progress done/> });
+        let id = attribute(&html, "<style", "data-style-id");
+        let wrapper = &html[html.find("<div").unwrap()..];
+        assert_eq!(attribute(wrapper, "<div", "class"), id);
+        assert!(wrapper.contains(text), "{}", html);
+    }
+}
+
+#[styles("card.css", forward(data-active))]
+#[component]
+fn ActiveCard() -> impl IntoView {
+    view! { <p class="card">"..."</p> }
+}
+
+#[test]
+fn forwarded_attributes_are_set_only_when_given() {
+    let html = render(|| view! { <ActiveCard data_active="yes"/> });
+    assert_eq!(attribute(&html, "<div", "data-active"), "yes");
+
+    let html = render(|| view! { <ActiveCard/> });
+    assert!(!html.contains("data-active"), "{}", html);
+}
+
+#[test]
+fn the_scope_constant_is_the_wrapper_class() {
+    let html = render(|| view! { <PlainCard/> });
+    assert_eq!(attribute(&html, "<div", "class"), PLAIN_CARD_SCOPE);
+}
+
+#[component]
+#[styles("card.css", inject = "mount")]
+fn MountedCard() -> impl IntoView {
+    view! { <p class="card">"..."</p> }
+}
+
+#[test]
+fn stylesheets_injected_on_mount_render_once_on_the_server() {
+    let html = render(|| view! { <MountedCard/><MountedCard/> });
+    assert_eq!(styles_of(&html).len(), 1, "{}", html);
+}
+
+#[component]
+#[styles("card.css", vars(accent, text_color = "white"))]
+fn Badge(accent: &'static str) -> impl IntoView {
+    view! { <span class="card">{accent}</span> }
+}
+
+#[test]
+fn vars_set_the_style_of_the_scope_element() {
+    let html = render(|| view! { <Badge accent="red"/> });
+    assert_eq!(
+        attribute(&html, "<div", "style"),
+        "--accent: red; --text-color: white;"
+    );
+}
+
+#[test]
+fn styled_views_are_scoped_like_components() {
+    let html = render(|| {
+        (0..2)
+            .map(|_| {
+                leptos_styles::styled!(
+                    "card.css",
+                    element = "li",
+                    view! { <span class="title">"Row"</span> }
+                )
+            })
+            .collect_view()
+    });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert!(id.starts_with("card"), "{}", id);
+    let items: Vec<&str> = html.split("<li").skip(1).collect();
+    assert_eq!(items.len(), 2, "{}", html);
+    assert!(items.iter().all(|li| attribute(li, "", "class") == id));
+    let css = styles_of(&html).concat();
+    assert!(
+        css.contains(&format!(".{} .card:hover .title", id)),
+        "{}",
+        css
+    );
+}
+
+#[component]
+#[styles("card.css", features(sass = "base.css"))]
+fn VariantCard() -> impl IntoView {
+    view! { <article class="card button">"..."</article> }
+}
+
+#[test]
+fn only_the_enabled_variant_is_rendered() {
+    let html = render(|| view! { <VariantCard/> });
+    let css = styles_of(&html).concat();
+    assert_eq!(css.contains(".button"), cfg!(feature = "sass"), "{}", css);
+    assert_eq!(css.contains(" .card"), !cfg!(feature = "sass"), "{}", css);
+}
+
+#[component]
+#[styles("card.css", wrapper = false)]
+fn FragmentCard() -> impl IntoView {
+    view! {
+        <>
+            <h2 class="title">"Title"</h2>
+            <>
+                <p class="card">"Body"</p>
+            </>
+        </>
+    }
+}
+
+#[test]
+fn rules_without_a_wrapper_match_the_elements_of_a_fragment() {
+    let html = render(|| view! { <FragmentCard/> });
+    let id = attribute(&html, "<style", "data-style-id");
+    assert_eq!(attribute(&html, "<h2", "class"), format!("{} title", id));
+    assert_eq!(attribute(&html, "<p", "class"), format!("{} card", id));
+    assert!(!html.contains("<div"), "{}", html);
+}